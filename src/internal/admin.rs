@@ -0,0 +1,454 @@
+use crate::internal::chaos::ChaosConfig;
+use crate::internal::chimera::{AppState, Config};
+use crate::internal::helpers::error_response;
+use crate::internal::journal::{clear_requests, list_requests, JOURNAL_PATH};
+use crate::internal::latency::Latency;
+use crate::internal::metrics::record_count;
+use crate::internal::proxy::RECORDINGS_KEY;
+use crate::internal::routes::ROUTES_KEY;
+use crate::internal::session::{Dataset, SESSION_PREFIX};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::info;
+
+// Reserved for controlling and inspecting the mock; never served from the data file
+pub const ADMIN_PREFIX: &str = "/__chimera";
+// Shown instead of credentials, as the admin API itself is unauthenticated
+pub(crate) const REDACTED: &str = "***";
+// `[auth]` fields that would let a caller sign tokens or pass the key check
+const SECRET_AUTH_FIELDS: [&str; 2] = ["secret", "api_key"];
+
+/// The `/__chimera` control API. It is merged after the latency, chaos, auth
+/// and rate limit layers, so none of them apply to it.
+pub fn admin_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            &format!("{}/data", ADMIN_PREFIX),
+            get(get_dataset).put(replace_dataset),
+        )
+        .route(&format!("{}/reset", ADMIN_PREFIX), post(reset_dataset))
+        .route(&format!("{}/snapshots", ADMIN_PREFIX), get(list_snapshots))
+        .route(
+            &format!("{}/snapshots/:name", ADMIN_PREFIX),
+            get(get_snapshot)
+                .post(save_snapshot)
+                .delete(delete_snapshot),
+        )
+        .route(
+            &format!("{}/snapshots/:name/restore", ADMIN_PREFIX),
+            post(restore_snapshot),
+        )
+        .route(
+            &format!("{}/settings", ADMIN_PREFIX),
+            get(get_settings).patch(update_settings),
+        )
+        .route(&format!("{}/routes", ADMIN_PREFIX), get(list_routes))
+        .route(&format!("{}/config", ADMIN_PREFIX), get(get_config))
+        .route(SESSION_PREFIX, get(list_sessions).delete(clear_sessions))
+        .route(&format!("{}/:id", SESSION_PREFIX), delete(delete_session))
+        .route(JOURNAL_PATH, get(list_requests).delete(clear_requests))
+}
+
+/// `GET /__chimera/data`
+async fn get_dataset(dataset: Dataset) -> Json<Value> {
+    Json(dataset.json_value.read().await.clone())
+}
+
+// Swap the data of the request's session, or the shared data outside one
+async fn swap_dataset(state: &AppState, dataset: &Dataset, data: Value, action: &str) {
+    *dataset.json_value.write().await = data;
+    dataset.mark_dirty(state);
+    if !state.logs_disabled {
+        match &dataset.session {
+            Some(id) => info!("Session {} dataset {} through {}", id, action, ADMIN_PREFIX),
+            None => info!("Dataset {} through {}", action, ADMIN_PREFIX),
+        }
+    }
+}
+
+/// `PUT /__chimera/data` replaces every collection with the posted object.
+async fn replace_dataset(
+    State(state): State<Arc<AppState>>,
+    dataset: Dataset,
+    Json(data): Json<Value>,
+) -> Response {
+    let Some(collections) = data.as_object() else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "The dataset must be a JSON object of routes",
+        );
+    };
+    if let Some(key) = [ROUTES_KEY, RECORDINGS_KEY]
+        .into_iter()
+        .find(|key| collections.contains_key(*key))
+    {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("`{}` can't be replaced at runtime", key),
+        );
+    }
+
+    swap_dataset(&state, &dataset, data.clone(), "replaced").await;
+    Json(data).into_response()
+}
+
+/// `POST /__chimera/reset` restores the data loaded at startup.
+async fn reset_dataset(State(state): State<Arc<AppState>>, dataset: Dataset) -> Json<Value> {
+    let data = state.snapshots.pristine().await;
+    swap_dataset(&state, &dataset, data.clone(), "reset").await;
+    Json(data)
+}
+
+/// `GET /__chimera/snapshots`
+async fn list_snapshots(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(Value::Array(state.snapshots.list()))
+}
+
+fn no_snapshot(name: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        format!("No snapshot named `{}`", name),
+    )
+}
+
+/// `GET /__chimera/snapshots/:name`
+async fn get_snapshot(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    match state.snapshots.get(&name) {
+        Some(data) => Json(data).into_response(),
+        None => no_snapshot(&name),
+    }
+}
+
+/// `POST /__chimera/snapshots/:name` saves the current dataset as `name`.
+async fn save_snapshot(
+    State(state): State<Arc<AppState>>,
+    dataset: Dataset,
+    Path(name): Path<String>,
+) -> Response {
+    let data = dataset.json_value.read().await.clone();
+    if let Err(e) = state.snapshots.save(&name, data).await {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+    if !state.logs_disabled {
+        info!("Saved snapshot {}", name);
+    }
+    (StatusCode::CREATED, Json(json!({ "name": name }))).into_response()
+}
+
+/// `POST /__chimera/snapshots/:name/restore`
+async fn restore_snapshot(
+    State(state): State<Arc<AppState>>,
+    dataset: Dataset,
+    Path(name): Path<String>,
+) -> Response {
+    let Some(data) = state.snapshots.get(&name) else {
+        return no_snapshot(&name);
+    };
+    swap_dataset(
+        &state,
+        &dataset,
+        data.clone(),
+        &format!("restored from snapshot {}", name),
+    )
+    .await;
+    Json(data).into_response()
+}
+
+/// `DELETE /__chimera/snapshots/:name`
+async fn delete_snapshot(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    if state.snapshots.delete(&name).await {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        no_snapshot(&name)
+    }
+}
+
+fn settings(state: &AppState) -> Value {
+    json!({
+        "latency": state.latency(),
+        "chaos": state.chaos(),
+    })
+}
+
+/// `GET /__chimera/settings`
+async fn get_settings(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(settings(&state))
+}
+
+// Each setting given replaces the current one; `chaos: {"rate": 0}` turns faults off
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsUpdate {
+    latency: Option<Latency>,
+    chaos: Option<ChaosConfig>,
+}
+
+/// `PATCH /__chimera/settings` changes the global latency and chaos settings.
+async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    Json(update): Json<SettingsUpdate>,
+) -> Json<Value> {
+    if let Some(latency) = update.latency {
+        *state.latency.write().unwrap_or_else(|e| e.into_inner()) = latency;
+    }
+    if let Some(chaos) = update.chaos {
+        // A new seed restarts the fault sequence, so a test can replay it
+        if let Some(seed) = chaos.seed {
+            state.chaos_rng.reseed(seed);
+        }
+        *state.chaos.write().unwrap_or_else(|e| e.into_inner()) = chaos;
+    }
+
+    let settings = settings(&state);
+    if !state.logs_disabled {
+        info!("Settings updated through {}: {}", ADMIN_PREFIX, settings);
+    }
+    Json(settings)
+}
+
+/// `GET /__chimera/routes` lists the collections in the dataset with their record
+/// counts, and routes that only exist as definitions.
+async fn list_routes(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let routes = state.routes();
+    let mut listing: BTreeMap<String, Value> = BTreeMap::new();
+
+    if let Some(collections) = state.json_value.read().await.as_object() {
+        for (route, value) in collections {
+            let records = record_count(value);
+            listing.insert(
+                route.clone(),
+                json!({
+                    "path": format!("/{}", route),
+                    "records": records,
+                    "configured": routes.contains(route),
+                }),
+            );
+        }
+    }
+    for route in routes.keys() {
+        listing.entry(route.to_string()).or_insert_with(|| {
+            json!({
+                "path": format!("/{}", route),
+                "records": null,
+                "configured": true,
+            })
+        });
+    }
+
+    Json(Value::Array(listing.into_values().collect()))
+}
+
+/// The startup config as `GET /__chimera/config` shows it, with secrets masked.
+pub fn public_config(config: &Config) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    redact_secrets(&mut value);
+    value
+}
+
+fn redact_secrets(config: &mut Value) {
+    if let Some(auth) = config.get_mut("auth").and_then(Value::as_object_mut) {
+        for field in SECRET_AUTH_FIELDS {
+            if let Some(secret) = auth.get_mut(field).filter(|secret| !secret.is_null()) {
+                *secret = json!(REDACTED);
+            }
+        }
+    }
+}
+
+/// `GET /__chimera/config` shows the settings the server started with, and the
+/// current latency and chaos settings.
+async fn get_config(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let mut config = state.config.clone();
+    if let (Some(config), Value::Object(settings)) = (config.as_object_mut(), settings(&state)) {
+        config.extend(settings);
+    }
+    Json(config)
+}
+
+/// `GET /__chimera/sessions`
+async fn list_sessions(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(Value::Array(state.sessions.list()))
+}
+
+/// `DELETE /__chimera/sessions/:id` drops a session; its next request starts over.
+async fn delete_session(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    if state.sessions.remove(&id) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        error_response(StatusCode::NOT_FOUND, format!("No session named `{}`", id))
+    }
+}
+
+/// `DELETE /__chimera/sessions`
+async fn clear_sessions(State(state): State<Arc<AppState>>) -> StatusCode {
+    state.sessions.clear();
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::session::SESSION_HEADER;
+    use axum::extract::FromRequestParts;
+    use axum::http::{Method, Request};
+
+    // The dataset a request with `method`, in `session` if given, is served from
+    async fn dataset(state: &Arc<AppState>, method: Method, session: Option<&str>) -> Dataset {
+        let mut request = Request::builder().method(method);
+        if let Some(id) = session {
+            request = request.header(SESSION_HEADER, id);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        Dataset::from_request_parts(&mut parts, state)
+            .await
+            .unwrap()
+    }
+
+    async fn shared(state: &Arc<AppState>) -> Dataset {
+        dataset(state, Method::POST, None).await
+    }
+
+    #[test]
+    fn masks_auth_secrets() {
+        let mut config = json!({
+            "port": 8080,
+            "auth": {"scheme": "bearer", "secret": "s3cret", "api_key": null, "issuer": "me"}
+        });
+        redact_secrets(&mut config);
+        assert_eq!(
+            config,
+            json!({
+                "port": 8080,
+                "auth": {"scheme": "bearer", "secret": "***", "api_key": null, "issuer": "me"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn replaces_the_dataset() {
+        let state = Arc::new(AppState::for_tests(json!({"users": []})));
+        let data = json!({"posts": [{"id": 1}]});
+
+        let response = replace_dataset(
+            State(state.clone()),
+            shared(&state).await,
+            Json(data.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(get_dataset(shared(&state).await).await.0, data);
+
+        for invalid in [json!([]), json!({"$routes": {}})] {
+            let response =
+                replace_dataset(State(state.clone()), shared(&state).await, Json(invalid)).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(*state.json_value.read().await, data);
+    }
+
+    #[tokio::test]
+    async fn updates_settings() {
+        let state = Arc::new(AppState::for_tests(json!({})));
+        let update = json!({"latency": "50..200ms", "chaos": {"rate": 50.0, "seed": 4}});
+
+        let settings = update_settings(
+            State(state.clone()),
+            Json(serde_json::from_value(update).unwrap()),
+        )
+        .await;
+        assert_eq!(settings["chaos"]["rate"], 50.0);
+        assert_eq!(state.latency(), Latency::parse("50..200ms").unwrap());
+        assert!(serde_json::from_value::<SettingsUpdate>(json!({"colour": 1})).is_err());
+    }
+
+    #[tokio::test]
+    async fn lists_routes_with_record_counts() {
+        let state = Arc::new(AppState::for_tests(
+            json!({"users": [{"id": 1}, {"id": 2}]}),
+        ));
+        let routes = json!({"health": {"body": "ok"}});
+        *state.routes.write().unwrap() = Arc::new(serde_json::from_value(routes).unwrap());
+
+        assert_eq!(
+            list_routes(State(state)).await.0,
+            json!([
+                {"path": "/health", "records": null, "configured": true},
+                {"path": "/users", "records": 2, "configured": false},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn restores_snapshots_and_resets() {
+        let pristine = json!({"users": [{"id": 1}]});
+        let state = Arc::new(AppState::for_tests(pristine.clone()));
+        let name = || Path("saved".to_string());
+
+        *state.json_value.write().await = json!({"users": []});
+        let response = save_snapshot(State(state.clone()), shared(&state).await, name()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        *state.json_value.write().await = json!({"users": [{"id": 2}]});
+        let response = restore_snapshot(State(state.clone()), shared(&state).await, name()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(*state.json_value.read().await, json!({"users": []}));
+
+        assert_eq!(
+            reset_dataset(State(state.clone()), shared(&state).await)
+                .await
+                .0,
+            pristine
+        );
+        assert_eq!(*state.json_value.read().await, pristine);
+
+        let response = restore_snapshot(
+            State(state.clone()),
+            shared(&state).await,
+            Path("missing".to_string()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let dataset = shared(&state).await;
+        let response = save_snapshot(State(state), dataset, Path("..".to_string())).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn session_resets_leave_everyone_else_alone() {
+        let pristine = json!({"users": [{"id": 1}]});
+        let state = Arc::new(AppState::for_tests(pristine.clone()));
+        *state.json_value.write().await = json!({"users": [{"id": 2}]});
+        let other = dataset(&state, Method::POST, Some("b")).await;
+        *other.json_value.write().await = json!({"users": [{"id": 3}]});
+
+        let session = dataset(&state, Method::POST, Some("a")).await;
+        *session.json_value.write().await = json!({"users": []});
+        let session = dataset(&state, Method::POST, Some("a")).await;
+        assert_eq!(
+            reset_dataset(State(state.clone()), session).await.0,
+            pristine
+        );
+
+        let session = dataset(&state, Method::GET, Some("a")).await;
+        assert_eq!(get_dataset(session).await.0, pristine);
+        assert_eq!(
+            *state.json_value.read().await,
+            json!({"users": [{"id": 2}]})
+        );
+        let other = dataset(&state, Method::GET, Some("b")).await;
+        assert_eq!(
+            *other.json_value.read().await,
+            json!({"users": [{"id": 3}]})
+        );
+        assert_eq!(state.snapshots.pristine().await, pristine);
+    }
+}
@@ -0,0 +1,543 @@
+use crate::internal::chimera::AppState;
+use crate::internal::helpers::error_response;
+use crate::internal::jwt::{self, Validation};
+use crate::internal::query::value_as_text;
+use axum::{
+    body::Bytes,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+pub const LOGIN_PATH: &str = "/auth/login";
+pub const REFRESH_PATH: &str = "/auth/refresh";
+
+const DEFAULT_API_KEY_HEADER: &str = "x-api-key";
+const DEFAULT_USERS: &str = "users";
+const DEFAULT_TOKEN_TTL_SECS: i64 = 3600;
+const DEFAULT_REFRESH_TTL_SECS: i64 = 7 * 24 * 3600;
+const REALM: &str = "chimera";
+
+/// How a route checks credentials.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    None,
+    Basic,
+    #[serde(alias = "api-key")]
+    ApiKey,
+    #[serde(alias = "jwt")]
+    Bearer,
+}
+
+impl AuthScheme {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.to_string())).ok()
+    }
+}
+
+/// Settings for the `[auth]` section. `scheme` protects every route; routes and
+/// methods can pick their own with `auth = "..."` and require `roles`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub scheme: Option<AuthScheme>,
+    // HS256 secret for issuing and checking bearer tokens
+    pub secret: Option<String>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    // Lifetimes of issued access and refresh tokens, in seconds
+    pub token_ttl: Option<i64>,
+    pub refresh_ttl: Option<i64>,
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+    // Collection holding the users that basic auth and `/auth/login` accept
+    pub users: Option<String>,
+    pub username_field: Option<String>,
+    pub password_field: Option<String>,
+}
+
+impl AuthConfig {
+    fn secret(&self) -> Option<&str> {
+        self.secret.as_deref().filter(|secret| !secret.is_empty())
+    }
+
+    fn users(&self) -> &str {
+        self.users.as_deref().unwrap_or(DEFAULT_USERS)
+    }
+
+    fn username_field(&self) -> &str {
+        self.username_field.as_deref().unwrap_or("username")
+    }
+
+    fn password_field(&self) -> &str {
+        self.password_field.as_deref().unwrap_or("password")
+    }
+
+    pub(crate) fn api_key_header(&self) -> String {
+        self.api_key_header
+            .as_deref()
+            .unwrap_or(DEFAULT_API_KEY_HEADER)
+            .to_ascii_lowercase()
+    }
+
+    /// Fail when `[auth] scheme`, or a route's `auth`, has nothing to check
+    /// credentials against, rather than turning every request away.
+    pub fn validate(&self, routes: impl IntoIterator<Item = AuthScheme>) -> Result<(), String> {
+        let unset = |value: &Option<String>| value.as_deref().is_none_or(str::is_empty);
+        for scheme in self.scheme.into_iter().chain(routes) {
+            match scheme {
+                AuthScheme::Bearer if unset(&self.secret) => {
+                    return Err(
+                        "Bearer auth needs a signing secret. Pass --jwt-secret or set `secret` under [auth]"
+                            .to_string(),
+                    )
+                }
+                AuthScheme::ApiKey if unset(&self.api_key) => {
+                    return Err(
+                        "API key auth needs a key. Pass --api-key or set `api_key` under [auth]"
+                            .to_string(),
+                    )
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn validation(&self) -> Validation<'_> {
+        Validation {
+            issuer: self.issuer.as_deref(),
+            audience: self.audience.as_deref(),
+        }
+    }
+}
+
+/// Why a request was turned away: 401 when credentials are missing or wrong,
+/// 403 when they are valid but not enough for the route.
+enum Rejection {
+    Missing,
+    Unauthorized(&'static str),
+    Forbidden(&'static str),
+}
+
+impl Rejection {
+    fn message(&self) -> &'static str {
+        match self {
+            Rejection::Missing => "Missing credentials",
+            Rejection::Unauthorized(message) | Rejection::Forbidden(message) => message,
+        }
+    }
+}
+
+fn challenge(scheme: AuthScheme, config: &AuthConfig, error: Option<&str>) -> String {
+    match scheme {
+        AuthScheme::Basic => format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM),
+        AuthScheme::ApiKey => format!(
+            "ApiKey realm=\"{}\", header=\"{}\"",
+            REALM,
+            config.api_key_header()
+        ),
+        _ => match error {
+            Some(error) => format!("Bearer realm=\"{}\", error=\"{}\"", REALM, error),
+            None => format!("Bearer realm=\"{}\"", REALM),
+        },
+    }
+}
+
+fn reject(rejection: Rejection, scheme: AuthScheme, config: &AuthConfig) -> Response {
+    // Bearer challenges only name an error when a token was actually sent
+    let (status, error) = match rejection {
+        Rejection::Missing => (StatusCode::UNAUTHORIZED, None),
+        Rejection::Unauthorized(_) => (StatusCode::UNAUTHORIZED, Some("invalid_token")),
+        Rejection::Forbidden(_) => (StatusCode::FORBIDDEN, Some("insufficient_scope")),
+    };
+    let mut response = error_response(status, rejection.message());
+    if let Ok(value) = HeaderValue::from_str(&challenge(scheme, config, error)) {
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, value);
+    }
+    response
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn has_role(claims_role: Option<&Value>, roles: &[String]) -> bool {
+    match claims_role {
+        Some(Value::Array(granted)) => granted
+            .iter()
+            .any(|role| roles.contains(&value_as_text(role))),
+        Some(role) => roles.contains(&value_as_text(role)),
+        None => false,
+    }
+}
+
+// A user record from the fixture collection whose username and password match
+async fn find_user(
+    state: &AppState,
+    config: &AuthConfig,
+    username: &str,
+    password: &str,
+) -> Option<Value> {
+    let data = state.json_value.read().await;
+    data.get(config.users())?
+        .as_array()?
+        .iter()
+        .find(|user| {
+            let field = |name: &str| user.get(name).map(value_as_text);
+            (field(config.username_field()).as_deref() == Some(username)
+                || field("email").as_deref() == Some(username))
+                && field(config.password_field()).as_deref() == Some(password)
+        })
+        .cloned()
+}
+
+async fn authenticate(
+    state: &AppState,
+    config: &AuthConfig,
+    scheme: AuthScheme,
+    roles: &[String],
+    headers: &HeaderMap,
+) -> Result<(), Rejection> {
+    let role = match scheme {
+        AuthScheme::None => return Ok(()),
+        AuthScheme::ApiKey => {
+            let key = header_str(headers, &config.api_key_header()).ok_or(Rejection::Missing)?;
+            if Some(key) != config.api_key.as_deref() {
+                return Err(Rejection::Forbidden("Invalid API key"));
+            }
+            // API keys carry no roles
+            None
+        }
+        AuthScheme::Basic => {
+            let encoded = header_str(headers, header::AUTHORIZATION.as_str())
+                .and_then(|value| value.strip_prefix("Basic "))
+                .ok_or(Rejection::Missing)?;
+            let decoded = STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or(Rejection::Unauthorized("Malformed basic credentials"))?;
+            let (username, password) = decoded
+                .split_once(':')
+                .ok_or(Rejection::Unauthorized("Malformed basic credentials"))?;
+            let user = find_user(state, config, username, password)
+                .await
+                .ok_or(Rejection::Unauthorized("Invalid username or password"))?;
+            user.get("role").cloned()
+        }
+        AuthScheme::Bearer => {
+            let token = header_str(headers, header::AUTHORIZATION.as_str())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or(Rejection::Missing)?;
+            let secret = config
+                .secret()
+                .ok_or(Rejection::Unauthorized("Invalid token"))?;
+            let claims =
+                jwt::decode(token.trim(), secret, &config.validation()).map_err(|e| match e {
+                    jwt::JwtError::Expired => Rejection::Unauthorized("Token has expired"),
+                    _ => Rejection::Unauthorized("Invalid token"),
+                })?;
+            if claims.get("typ").and_then(Value::as_str) == Some("refresh") {
+                return Err(Rejection::Unauthorized(
+                    "Refresh tokens can't access resources",
+                ));
+            }
+            claims.get("role").cloned()
+        }
+    };
+
+    if !roles.is_empty() && !has_role(role.as_ref(), roles) {
+        return Err(Rejection::Forbidden("Insufficient role"));
+    }
+    Ok(())
+}
+
+/// Guard routes with the scheme configured for them (method, then route, then
+/// the `[auth]` default). The login and refresh endpoints are always open.
+pub async fn require_auth(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.auth;
+    let path = request.uri().path();
+    if path == LOGIN_PATH || path == REFRESH_PATH {
+        return next.run(request).await;
+    }
+
+    let route = path.trim_start_matches('/').to_string();
+    let method = request.method().clone();
+    let (scheme, roles) = state.auth_for(&route, method.as_str());
+    if scheme == AuthScheme::None {
+        return next.run(request).await;
+    }
+    if let Err(rejection) = authenticate(&state, config, scheme, &roles, request.headers()).await {
+        if !state.logs_disabled {
+            warn!(
+                method = method.as_str(),
+                path = request.uri().path(),
+                error = rejection.message(),
+                "Authentication failed"
+            );
+        }
+        return reject(rejection, scheme, config);
+    }
+    next.run(request).await
+}
+
+// Issue an access token and a refresh token for `claims`, signed with `secret`
+fn issue_tokens(config: &AuthConfig, secret: &str, mut claims: Map<String, Value>) -> Value {
+    let now = Utc::now().timestamp();
+    let token_ttl = config.token_ttl.unwrap_or(DEFAULT_TOKEN_TTL_SECS);
+    let refresh_ttl = config.refresh_ttl.unwrap_or(DEFAULT_REFRESH_TTL_SECS);
+
+    claims.insert("iat".to_string(), json!(now));
+    if let Some(issuer) = &config.issuer {
+        claims.insert("iss".to_string(), json!(issuer));
+    }
+    if let Some(audience) = &config.audience {
+        claims.insert("aud".to_string(), json!(audience));
+    }
+    claims.remove("typ");
+
+    let mut access = claims.clone();
+    access.insert("exp".to_string(), json!(now + token_ttl));
+    let mut refresh = claims;
+    refresh.insert("exp".to_string(), json!(now + refresh_ttl));
+    refresh.insert("typ".to_string(), json!("refresh"));
+
+    json!({
+        "access_token": jwt::encode(&access, secret),
+        "token_type": "Bearer",
+        "expires_in": token_ttl,
+        "refresh_token": jwt::encode(&refresh, secret),
+    })
+}
+
+// Login bodies may be JSON or a form
+fn credentials(body: &[u8]) -> Map<String, Value> {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| value.as_object().cloned())
+        .or_else(|| {
+            serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+                .ok()
+                .map(|pairs| {
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| (k, Value::String(v)))
+                        .collect()
+                })
+        })
+        .unwrap_or_default()
+}
+
+// Tokens are only signed with a configured secret, never a built-in one
+fn no_secret(state: &AppState, path: &str, start_time: Instant, date_time: &str) -> Response {
+    log_auth(state, StatusCode::NOT_FOUND, path, start_time, date_time);
+    error_response(
+        StatusCode::NOT_FOUND,
+        "Tokens need a signing secret. Pass --jwt-secret or set `secret` under [auth]",
+    )
+}
+
+fn log_auth(
+    state: &AppState,
+    status: StatusCode,
+    path: &str,
+    start_time: Instant,
+    date_time: &str,
+) {
+    if !state.logs_disabled {
+        info!(
+            date_time = date_time,
+            status = status.as_str(),
+            method = "POST",
+            path = path,
+            elapsed_ms = start_time.elapsed().as_millis(),
+            records = usize::from(status.is_success()),
+            "HTTP request"
+        );
+    }
+}
+
+/// `POST /auth/login` with `username` (or `email`) and `password`: tokens for a
+/// user in the fixture collection.
+pub async fn login(State(state): State<Arc<AppState>>, body: Bytes) -> Response {
+    let start_time = Instant::now();
+    let date_time = Local::now().format("%Y/%m/%d - %H:%M:%S").to_string();
+    let config = &state.auth;
+    let Some(secret) = config.secret() else {
+        return no_secret(&state, LOGIN_PATH, start_time, &date_time);
+    };
+
+    let fields = credentials(&body);
+    let field = |name: &str| fields.get(name).map(value_as_text);
+    let username = field(config.username_field()).or_else(|| field("email"));
+    let (Some(username), Some(password)) = (username, field(config.password_field())) else {
+        log_auth(
+            &state,
+            StatusCode::BAD_REQUEST,
+            LOGIN_PATH,
+            start_time,
+            &date_time,
+        );
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Username and password are required",
+        );
+    };
+
+    let Some(user) = find_user(&state, config, &username, &password).await else {
+        log_auth(
+            &state,
+            StatusCode::UNAUTHORIZED,
+            LOGIN_PATH,
+            start_time,
+            &date_time,
+        );
+        return reject(
+            Rejection::Unauthorized("Invalid username or password"),
+            AuthScheme::Bearer,
+            config,
+        );
+    };
+
+    let id_field = state.id_field_for(config.users());
+    let mut claims = Map::new();
+    if let Some(id) = user.get(&id_field) {
+        claims.insert("sub".to_string(), Value::String(value_as_text(id)));
+    }
+    claims.insert("name".to_string(), Value::String(username));
+    if let Some(role) = user.get("role") {
+        claims.insert("role".to_string(), role.clone());
+    }
+
+    log_auth(&state, StatusCode::OK, LOGIN_PATH, start_time, &date_time);
+    Json(issue_tokens(config, secret, claims)).into_response()
+}
+
+/// `POST /auth/refresh` with a `refresh_token`: a new pair of tokens.
+pub async fn refresh(State(state): State<Arc<AppState>>, body: Bytes) -> Response {
+    let start_time = Instant::now();
+    let date_time = Local::now().format("%Y/%m/%d - %H:%M:%S").to_string();
+    let config = &state.auth;
+    let Some(secret) = config.secret() else {
+        return no_secret(&state, REFRESH_PATH, start_time, &date_time);
+    };
+
+    let fields = credentials(&body);
+    let Some(token) = fields.get("refresh_token").and_then(Value::as_str) else {
+        log_auth(
+            &state,
+            StatusCode::BAD_REQUEST,
+            REFRESH_PATH,
+            start_time,
+            &date_time,
+        );
+        return error_response(StatusCode::BAD_REQUEST, "refresh_token is required");
+    };
+
+    let claims = jwt::decode(token, secret, &config.validation())
+        .ok()
+        .filter(|claims| claims.get("typ").and_then(Value::as_str) == Some("refresh"));
+    let Some(mut claims) = claims else {
+        log_auth(
+            &state,
+            StatusCode::UNAUTHORIZED,
+            REFRESH_PATH,
+            start_time,
+            &date_time,
+        );
+        return reject(
+            Rejection::Unauthorized("Invalid refresh token"),
+            AuthScheme::Bearer,
+            config,
+        );
+    };
+    claims.remove("exp");
+
+    log_auth(&state, StatusCode::OK, REFRESH_PATH, start_time, &date_time);
+    Json(issue_tokens(config, secret, claims)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scheme_names() {
+        assert_eq!(AuthScheme::parse("api-key"), Some(AuthScheme::ApiKey));
+        assert_eq!(AuthScheme::parse("jwt"), Some(AuthScheme::Bearer));
+        assert_eq!(AuthScheme::parse("oauth"), None);
+    }
+
+    #[test]
+    fn schemes_need_their_secret() {
+        let bearer = AuthConfig {
+            scheme: Some(AuthScheme::Bearer),
+            ..Default::default()
+        };
+        assert!(bearer.validate([]).is_err());
+        let bearer = AuthConfig {
+            secret: Some("s".to_string()),
+            ..bearer
+        };
+        assert!(bearer.validate([]).is_ok());
+
+        // A route asking for a scheme counts as much as `[auth] scheme`
+        let config = AuthConfig::default();
+        assert!(config.validate([AuthScheme::Basic]).is_ok());
+        assert!(config.validate([AuthScheme::ApiKey]).is_err());
+        let config = AuthConfig {
+            api_key: Some(String::new()),
+            ..config
+        };
+        assert!(config.validate([AuthScheme::ApiKey]).is_err());
+    }
+
+    #[tokio::test]
+    async fn logins_need_a_configured_secret() {
+        let data = json!({"users": [{"id": 1, "username": "ann", "password": "pw"}]});
+        let body = Bytes::from_static(br#"{"username": "ann", "password": "pw"}"#);
+
+        let state = Arc::new(AppState::for_tests(data.clone()));
+        let response = login(State(state.clone()), body.clone()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = refresh(State(state), Bytes::from_static(b"refresh_token=x")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let state = Arc::new(AppState {
+            auth: AuthConfig {
+                secret: Some("s3cret".to_string()),
+                ..Default::default()
+            },
+            ..AppState::for_tests(data)
+        });
+        let response = login(State(state), body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens: Value = serde_json::from_slice(
+            &axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        let token = tokens["access_token"].as_str().unwrap();
+        assert!(jwt::decode(token, "s3cret", &Validation::default()).is_ok());
+        assert!(jwt::decode(token, "chimera-secret", &Validation::default()).is_err());
+    }
+}
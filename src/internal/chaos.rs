@@ -0,0 +1,300 @@
+use crate::internal::chimera::AppState;
+use crate::internal::helpers::error_response;
+use crate::internal::latency::drip;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::{stream, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+use tracing::warn;
+
+// Chunk size and default pause for `slow` (slow-drip) responses
+const DRIP_CHUNK_BYTES: usize = 64;
+const DEFAULT_DRIP_MS: u64 = 100;
+// Truncated and malformed bodies are cut from at most this much of the response
+const MAX_CHAOS_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    #[serde(rename = "500")]
+    InternalError,
+    #[serde(rename = "503")]
+    Unavailable,
+    #[serde(rename = "429")]
+    TooManyRequests,
+    #[serde(rename = "drop")]
+    Drop,
+    #[serde(rename = "truncate")]
+    Truncate,
+    #[serde(rename = "malformed")]
+    Malformed,
+    #[serde(rename = "slow")]
+    Slow,
+}
+
+pub const ALL_FAULTS: &[Fault] = &[
+    Fault::InternalError,
+    Fault::Unavailable,
+    Fault::TooManyRequests,
+    Fault::Drop,
+    Fault::Truncate,
+    Fault::Malformed,
+    Fault::Slow,
+];
+
+impl Fault {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fault::InternalError => "500",
+            Fault::Unavailable => "503",
+            Fault::TooManyRequests => "429",
+            Fault::Drop => "drop",
+            Fault::Truncate => "truncate",
+            Fault::Malformed => "malformed",
+            Fault::Slow => "slow",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        ALL_FAULTS.iter().copied().find(|f| f.as_str() == name)
+    }
+}
+
+/// Fault injection settings, at the top level of the config file or under a
+/// route or method. `seed` is only read at the top level.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    // Percentage of requests (0-100) that get a fault
+    pub rate: Option<f64>,
+    pub faults: Option<Vec<Fault>>,
+    pub drip_ms: Option<u64>,
+    pub seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    pub fn overlay(&mut self, other: &ChaosConfig) {
+        self.rate = other.rate.or(self.rate);
+        self.faults = other.faults.clone().or(self.faults.take());
+        self.drip_ms = other.drip_ms.or(self.drip_ms);
+        self.seed = other.seed.or(self.seed);
+    }
+}
+
+// Shared RNG so a `seed` reproduces the same sequence of faults
+pub struct ChaosRng(Mutex<StdRng>);
+
+impl ChaosRng {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        ChaosRng(Mutex::new(rng))
+    }
+
+    pub fn reseed(&self, seed: u64) {
+        if let Ok(mut rng) = self.0.lock() {
+            *rng = StdRng::seed_from_u64(seed);
+        }
+    }
+
+    // Decide whether this request gets a fault, and which one
+    pub fn roll(&self, config: &ChaosConfig) -> Option<Fault> {
+        let rate = config.rate.unwrap_or(0.0);
+        if rate <= 0.0 {
+            return None;
+        }
+        let faults = config.faults.as_deref().unwrap_or(ALL_FAULTS);
+        if faults.is_empty() {
+            return None;
+        }
+
+        let mut rng = self.0.lock().ok()?;
+        if rng.random_range(0.0..100.0) >= rate {
+            return None;
+        }
+        Some(faults[rng.random_range(0..faults.len())])
+    }
+}
+
+fn tag(response: &mut Response, fault: Fault) {
+    response
+        .headers_mut()
+        .insert("x-chimera-fault", HeaderValue::from_static(fault.as_str()));
+}
+
+// The first `limit` bytes of `body`, or all of it if shorter. Whatever follows
+// is never read, so a large body isn't held in memory only to be cut.
+async fn read_head(body: Body, limit: usize) -> Result<Vec<u8>, axum::Error> {
+    let mut stream = body.into_data_stream();
+    let mut head = Vec::new();
+    while head.len() < limit {
+        match stream.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    head.truncate(limit);
+    Ok(head)
+}
+
+// Headers go out, then the body errors and hyper aborts the connection
+fn dropped_connection() -> Response {
+    let body = Body::from_stream(stream::once(async {
+        Err::<Bytes, std::io::Error>(std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            "chaos: connection dropped",
+        ))
+    }));
+    (StatusCode::OK, body).into_response()
+}
+
+/// Inject the configured faults before (status codes, dropped connections) or
+/// after (truncated, malformed, slow-drip bodies) the real handler runs.
+pub async fn chaos_faults(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request.uri().path().trim_start_matches('/').to_string();
+    let method = request.method().clone();
+    let config = state.chaos_for(&route, method.as_str());
+    let Some(fault) = state.chaos_rng.roll(&config) else {
+        return next.run(request).await;
+    };
+
+    if !state.logs_disabled {
+        warn!(
+            chaos = fault.as_str(),
+            method = method.as_str(),
+            path = request.uri().path(),
+            "Chaos fault injected"
+        );
+    }
+
+    let mut response = match fault {
+        Fault::InternalError => error_response(StatusCode::INTERNAL_SERVER_ERROR, "Injected fault"),
+        Fault::Unavailable => error_response(StatusCode::SERVICE_UNAVAILABLE, "Injected fault"),
+        Fault::TooManyRequests => {
+            let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, "Injected fault");
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+            response
+        }
+        Fault::Drop => dropped_connection(),
+        Fault::Slow => {
+            let (mut parts, body) = next.run(request).await.into_parts();
+            parts.headers.remove(header::CONTENT_LENGTH);
+            let pause = config.drip_ms.unwrap_or(DEFAULT_DRIP_MS);
+            let body = drip(body, DRIP_CHUNK_BYTES, Duration::from_millis(pause));
+            Response::from_parts(parts, body)
+        }
+        Fault::Truncate | Fault::Malformed => {
+            let (mut parts, body) = next.run(request).await.into_parts();
+            let Ok(head) = read_head(body, MAX_CHAOS_BODY_BYTES).await else {
+                return dropped_connection();
+            };
+            parts.headers.remove(header::CONTENT_LENGTH);
+            let mut broken = head[..head.len() / 2].to_vec();
+            if fault == Fault::Malformed {
+                // Cut mid-document and leave an unbalanced, invalid tail
+                broken.extend_from_slice(b"\"}{,");
+            }
+            Response::from_parts(parts, Body::from(broken))
+        }
+    };
+    tag(&mut response, fault);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    fn config(rate: f64, faults: Option<Vec<Fault>>) -> ChaosConfig {
+        ChaosConfig {
+            rate: Some(rate),
+            faults,
+            ..ChaosConfig::default()
+        }
+    }
+
+    fn rolls(rng: &ChaosRng, config: &ChaosConfig) -> Vec<Option<Fault>> {
+        (0..50).map(|_| rng.roll(config)).collect()
+    }
+
+    #[test]
+    fn fault_names_round_trip() {
+        for fault in ALL_FAULTS {
+            assert_eq!(Fault::parse(fault.as_str()), Some(*fault));
+        }
+        assert_eq!(Fault::parse("404"), None);
+    }
+
+    #[test]
+    fn a_seed_repeats_the_same_faults() {
+        let config = config(50.0, None);
+        let first = rolls(&ChaosRng::new(Some(7)), &config);
+        assert_eq!(first, rolls(&ChaosRng::new(Some(7)), &config));
+        assert!(first.iter().any(Option::is_some));
+        assert!(first.iter().any(Option::is_none));
+
+        let rng = ChaosRng::new(Some(1));
+        rng.reseed(7);
+        assert_eq!(first, rolls(&rng, &config));
+    }
+
+    #[test]
+    fn rate_bounds_and_fault_list() {
+        let rng = ChaosRng::new(Some(3));
+        assert!(rolls(&rng, &config(0.0, None)).iter().all(Option::is_none));
+        assert!(rolls(&rng, &config(100.0, Some(vec![])))
+            .iter()
+            .all(Option::is_none));
+        assert!(rolls(&rng, &config(100.0, Some(vec![Fault::Slow])))
+            .iter()
+            .all(|fault| *fault == Some(Fault::Slow)));
+    }
+
+    #[test]
+    fn route_settings_layer_over_global_ones() {
+        let mut base = config(10.0, Some(vec![Fault::Drop]));
+        base.seed = Some(1);
+        base.overlay(&ChaosConfig {
+            rate: Some(90.0),
+            ..ChaosConfig::default()
+        });
+        assert_eq!(base.rate, Some(90.0));
+        assert_eq!(base.faults, Some(vec![Fault::Drop]));
+        assert_eq!(base.seed, Some(1));
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_fail_mid_body() {
+        let response = dropped_connection();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(to_bytes(response.into_body(), usize::MAX).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_no_more_than_the_head() {
+        let chunks = ["abc", "def", "ghi"].map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk)));
+        let body = Body::from_stream(stream::iter(chunks));
+        assert_eq!(read_head(body, 4).await.unwrap(), b"abcd");
+        assert_eq!(read_head(Body::from("ab"), 4).await.unwrap(), b"ab");
+        assert!(read_head(dropped_connection().into_body(), 4)
+            .await
+            .is_err());
+    }
+}
@@ -1,8 +1,21 @@
+use crate::internal::auth::{AuthConfig, AuthScheme};
+use crate::internal::chaos::{ChaosConfig, ChaosRng};
+use crate::internal::journal::{Journal, JournalConfig};
+use crate::internal::latency::Latency;
+use crate::internal::metrics::Metrics;
+use crate::internal::persist::{JsonStyle, Persister};
+use crate::internal::proxy::{ProxyConfig, Recording};
+use crate::internal::query::NullsOrder;
+use crate::internal::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::internal::reload::ReloadEvent;
+use crate::internal::routes::{current_routes, RouteTable, SharedRoutes};
+use crate::internal::session::{SessionConfig, SessionStore};
+use crate::internal::snapshot::{Snapshot, SnapshotConfig, SnapshotStore};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -11,28 +24,273 @@ pub struct Config {
     pub mode: String,
     #[serde(skip)]
     pub json_value: Arc<RwLock<Value>>,
-    pub latency: u64,
+    pub latency: Latency,
+    // Response bandwidth in bytes/sec, 0 for unlimited
+    pub bandwidth: u64,
     pub sort_rules: HashMap<String, (String, String)>,
+    pub nulls: NullsOrder,
     pub paginate: u64,
+    pub envelope: bool,
+    pub id_field: String,
+    pub single_as_array: bool,
+    pub chaos: ChaosConfig,
+    pub rate_limit: RateLimitConfig,
+    pub auth: AuthConfig,
+    pub journal: JournalConfig,
+    pub snapshots: SnapshotConfig,
+    pub sessions: SessionConfig,
+    // Snapshots found in `snapshots.dir` at startup
+    #[serde(skip)]
+    pub saved_snapshots: BTreeMap<String, Snapshot>,
+    pub proxy: ProxyConfig,
+    // `$recordings` from the data file, replayed by `proxy`
+    #[serde(skip)]
+    pub recordings: Vec<Recording>,
     pub max_request_path_id_length: usize,
     pub max_request_path_len: usize,
     pub cors_enabled: bool,
     pub logs_disabled: bool,
     pub allowed_origins: Vec<String>,
+    pub persist_to: Option<String>,
+    #[serde(skip)]
+    pub persist_style: JsonStyle,
+    // Raw `$routes` from the data file, written back alongside persisted data
+    #[serde(skip)]
+    pub route_definitions: Option<Value>,
+    // Top-level keys in data file order, kept when writing back
+    #[serde(skip)]
+    pub key_order: Vec<String>,
+    pub auto_generate: bool,
+    pub watch: bool,
+    pub cors_file: Option<String>,
+    pub routes: RouteTable,
+    // Routes from the config file alone, re-merged when the data file reloads
+    #[serde(skip)]
+    pub file_routes: RouteTable,
 }
 
 pub struct AppState {
     pub json_value: Arc<RwLock<Value>>,
-    pub latency: u64,
+    // Named snapshots and the data as loaded at startup
+    pub snapshots: Arc<SnapshotStore>,
+    // Per-session copies of `json_value`, selected by `X-Chimera-Session`
+    pub sessions: Arc<SessionStore>,
+    // Startup `Config` with secrets masked, shown by `GET /__chimera/config`
+    pub config: Value,
+    // Latency and chaos can be changed at runtime through `/__chimera/settings`
+    pub latency: std::sync::RwLock<Latency>,
+    pub bandwidth: u64,
     pub sort_rules: HashMap<String, (String, String)>,
+    pub nulls: NullsOrder,
     pub paginate: u64,
+    pub envelope: bool,
+    pub id_field: String,
+    pub single_as_array: bool,
+    pub chaos: std::sync::RwLock<ChaosConfig>,
+    pub chaos_rng: ChaosRng,
+    pub rate_limit: RateLimitConfig,
+    pub rate_limiter: RateLimiter,
+    pub auth: AuthConfig,
+    pub journal: Journal,
+    pub metrics: Metrics,
     pub logs_disabled: bool,
+    pub persister: Option<Arc<Persister>>,
+    pub routes: SharedRoutes,
+}
+
+impl AppState {
+    pub fn routes(&self) -> Arc<RouteTable> {
+        current_routes(&self.routes)
+    }
+
+    pub fn latency(&self) -> Latency {
+        *self.latency.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn chaos(&self) -> ChaosConfig {
+        self.chaos.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // The method's latency, then the route's, then `--latency`
+    pub fn latency_for(&self, route: &str, method: &str) -> Latency {
+        self.routes()
+            .get(route)
+            .and_then(|config| config.response_for(method).latency)
+            .unwrap_or_else(|| self.latency())
+    }
+
+    pub fn bandwidth_for(&self, route: &str, method: &str) -> u64 {
+        self.routes()
+            .get(route)
+            .and_then(|config| config.response_for(method).bandwidth)
+            .unwrap_or(self.bandwidth)
+    }
+
+    pub fn page_size_for(&self, route: &str) -> u64 {
+        self.routes()
+            .get(route)
+            .and_then(|config| config.page)
+            .unwrap_or(self.paginate)
+    }
+
+    // Primary key for records under `route`; a route's `id_field` wins over `--id-field`
+    pub fn id_field_for(&self, route: &str) -> String {
+        self.routes()
+            .get(route)
+            .and_then(|config| config.id_field.clone())
+            .unwrap_or_else(|| self.id_field.clone())
+    }
+
+    // `--sort` on the command line, then the route's `sort`/`order` settings
+    pub fn sort_rule_for(&self, route: &str) -> Option<(String, String)> {
+        sort_rule_for(&self.sort_rules, &self.routes(), route)
+    }
+
+    // Global chaos settings, then the route's, then the method's
+    pub fn chaos_for(&self, route: &str, method: &str) -> ChaosConfig {
+        let mut chaos = self.chaos();
+        if let Some(config) = self.routes().get(route) {
+            if let Some(overrides) = config.response_for(method).chaos {
+                chaos.overlay(&overrides);
+            }
+        }
+        chaos
+    }
+
+    // The limit and bucket scope for a request, when one applies
+    pub fn rate_limit_for(&self, route: &str, method: &str) -> Option<(String, RateLimitConfig)> {
+        rate_limit_for(&self.rate_limit, &self.routes(), route, method)
+    }
+
+    // The method's `auth`, then the route's, then `[auth] scheme`; plus required roles
+    pub fn auth_for(&self, route: &str, method: &str) -> (AuthScheme, Vec<String>) {
+        let overrides = self
+            .routes()
+            .get(route)
+            .map(|config| config.response_for(method))
+            .unwrap_or_default();
+        let scheme = overrides
+            .auth
+            .or(self.auth.scheme)
+            .unwrap_or(AuthScheme::None);
+        (scheme, overrides.roles.unwrap_or_default())
+    }
+
+    // Fields `?q=` searches; empty means every string field
+    pub fn search_fields_for(&self, route: &str) -> Vec<String> {
+        self.routes()
+            .get(route)
+            .and_then(|config| config.search_fields.clone())
+            .unwrap_or_default()
+    }
+
+    // Called after a successful mutation so `--persist` can write it back
+    pub fn mark_dirty(&self) {
+        if let Some(persister) = &self.persister {
+            persister.schedule();
+        }
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    // Defaults for everything but the dataset, as if started with no flags
+    pub fn for_tests(data: Value) -> Self {
+        AppState {
+            json_value: Arc::new(RwLock::new(data.clone())),
+            snapshots: Arc::new(SnapshotStore::new(data, BTreeMap::new(), None)),
+            sessions: Arc::new(SessionStore::new(&SessionConfig::default())),
+            config: Value::Null,
+            latency: Default::default(),
+            bandwidth: 0,
+            sort_rules: HashMap::new(),
+            nulls: NullsOrder::default(),
+            paginate: 0,
+            envelope: false,
+            id_field: "id".to_string(),
+            single_as_array: false,
+            chaos: Default::default(),
+            chaos_rng: ChaosRng::new(None),
+            rate_limit: RateLimitConfig::default(),
+            rate_limiter: RateLimiter::default(),
+            auth: AuthConfig::default(),
+            journal: Journal::spawn(&JournalConfig::default()),
+            metrics: Metrics::default(),
+            logs_disabled: true,
+            persister: None,
+            routes: crate::internal::routes::shared_routes(RouteTable::default()),
+        }
+    }
 }
 
 pub struct AppStateWs {
     pub sort_rules: HashMap<String, (String, String)>,
+    pub nulls: NullsOrder,
     pub paginate: u64,
     pub logs_disabled: bool,
+    pub reload_tx: broadcast::Sender<ReloadEvent>,
+    pub routes: SharedRoutes,
+    pub rate_limit: RateLimitConfig,
+    pub rate_limiter: RateLimiter,
+    pub snapshots: Arc<SnapshotStore>,
+}
+
+impl AppStateWs {
+    pub fn page_size_for(&self, route: &str) -> u64 {
+        current_routes(&self.routes)
+            .get(route)
+            .and_then(|config| config.page)
+            .unwrap_or(self.paginate)
+    }
+
+    pub fn sort_rule_for(&self, route: &str) -> Option<(String, String)> {
+        sort_rule_for(&self.sort_rules, &current_routes(&self.routes), route)
+    }
+
+    // Messages on a socket are limited by the global and route-level settings
+    pub fn rate_limit_for(&self, route: &str) -> Option<(String, RateLimitConfig)> {
+        rate_limit_for(&self.rate_limit, &current_routes(&self.routes), route, "")
+    }
+}
+
+fn sort_rule_for(
+    sort_rules: &HashMap<String, (String, String)>,
+    routes: &RouteTable,
+    route: &str,
+) -> Option<(String, String)> {
+    sort_rules.get(route).cloned().or_else(|| {
+        let config = routes.get(route)?;
+        let key = config.sort.clone()?;
+        Some((
+            config.order.clone().unwrap_or_else(|| "asc".to_string()),
+            key,
+        ))
+    })
+}
+
+// Each route (or route and method) with its own limit gets its own buckets
+fn rate_limit_for(
+    global: &RateLimitConfig,
+    routes: &RouteTable,
+    route: &str,
+    method: &str,
+) -> Option<(String, RateLimitConfig)> {
+    let mut config = global.clone();
+    let mut scope = String::new();
+    if let Some((key, route_config, _)) = routes.find_entry(route) {
+        if let Some(limit) = &route_config.rate_limit {
+            config.overlay(limit);
+            scope = key.to_string();
+        }
+        if let Some(limit) = route_config
+            .method(method)
+            .and_then(|overrides| overrides.rate_limit.as_ref())
+        {
+            config.overlay(limit);
+            scope = format!("{} {}", method.to_ascii_uppercase(), key);
+        }
+    }
+    config.is_enabled().then_some((scope, config))
 }
 
 pub const CHIMERA_LATEST_VERSION: &str = "0.6.9";
@@ -0,0 +1,154 @@
+use crate::internal::auth::AuthConfig;
+use crate::internal::chaos::ChaosConfig;
+use crate::internal::journal::JournalConfig;
+use crate::internal::latency::Latency;
+use crate::internal::loader::file_extension;
+use crate::internal::proxy::ProxyConfig;
+use crate::internal::query::NullsOrder;
+use crate::internal::rate_limit::RateLimitConfig;
+use crate::internal::routes::RouteConfig;
+use crate::internal::session::SessionConfig;
+use crate::internal::snapshot::SnapshotConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Looked up in the working directory when `--config` isn't given
+pub const CONFIG_FILES: &[&str] = &["chimera.toml", "chimera.yaml", "chimera.yml"];
+
+/// Project-level settings from `chimera.toml` / `chimera.yaml`. Every field is
+/// optional; flags given on the command line take precedence.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub path: Option<String>,
+    pub mode: Option<String>,
+    pub port: Option<u16>,
+    pub latency: Option<Latency>,
+    pub bandwidth: Option<u64>,
+    pub page: Option<u64>,
+    pub envelope: Option<bool>,
+    pub id_field: Option<String>,
+    pub single_as_array: Option<bool>,
+    pub chaos: Option<ChaosConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub auth: Option<AuthConfig>,
+    pub journal: Option<JournalConfig>,
+    pub snapshots: Option<SnapshotConfig>,
+    pub sessions: Option<SessionConfig>,
+    pub proxy: Option<ProxyConfig>,
+    pub nulls: Option<NullsOrder>,
+    pub quiet: Option<bool>,
+    pub auto_generate_data: Option<bool>,
+    pub cors: Option<bool>,
+    pub cors_origins: Option<Vec<String>>,
+    pub watch: Option<bool>,
+    pub persist: Option<bool>,
+    pub persist_to: Option<String>,
+    pub routes: HashMap<String, RouteConfig>,
+}
+
+impl FileConfig {
+    pub fn parse(content: &str, path: &str) -> Result<Self, String> {
+        match file_extension(path).as_str() {
+            "toml" => toml::from_str(content).map_err(|e| format!("Invalid {}: {}", path, e)),
+            "yaml" | "yml" => {
+                serde_norway::from_str(content).map_err(|e| format!("Invalid {}: {}", path, e))
+            }
+            _ => Err(format!(
+                "Unsupported config file {}. Use a .toml, .yaml or .yml file",
+                path
+            )),
+        }
+    }
+
+    // `--config` wins; otherwise the first config file found in the working directory
+    pub async fn load(explicit: Option<&str>) -> Result<Option<(Self, PathBuf)>, String> {
+        let path = match explicit {
+            Some(path) => PathBuf::from(path),
+            None => match CONFIG_FILES.iter().map(PathBuf::from).find(|p| p.exists()) {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+        };
+
+        let display = path.display().to_string();
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", display, e))?;
+        let mut config = Self::parse(&content, &display)?;
+
+        // Relative paths in the file are relative to the file itself
+        let base = path.parent().unwrap_or(Path::new(""));
+        config.path = config.path.map(|p| resolve(base, &p));
+        config.persist_to = config.persist_to.map(|p| resolve(base, &p));
+        if let Some(journal) = config.journal.as_mut() {
+            journal.file = journal.file.take().map(|p| resolve(base, &p));
+        }
+        if let Some(snapshots) = config.snapshots.as_mut() {
+            snapshots.dir = snapshots.dir.take().map(|p| resolve(base, &p));
+        }
+
+        Ok(Some((config, path)))
+    }
+}
+
+fn resolve(base: &Path, path: &str) -> String {
+    if Path::new(path).is_absolute() || base.as_os_str().is_empty() {
+        path.to_string()
+    } else {
+        base.join(path).display().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml() {
+        let config = FileConfig::parse(
+            "port = 9000\nwatch = true\n\n[routes.users]\nstatus = 201\n",
+            "chimera.toml",
+        )
+        .unwrap();
+        assert_eq!(config.port, Some(9000));
+        assert_eq!(config.watch, Some(true));
+        assert!(config.routes.contains_key("users"));
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let config = FileConfig::parse(
+            "port: 9000\ncors_origins:\n  - http://localhost:3000\nroutes:\n  users:\n    status: 201\n",
+            "chimera.yml",
+        )
+        .unwrap();
+        assert_eq!(config.port, Some(9000));
+        assert_eq!(
+            config.cors_origins,
+            Some(vec!["http://localhost:3000".to_string()])
+        );
+        assert!(config.routes.contains_key("users"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_formats() {
+        assert!(FileConfig::parse("prot = 9000", "chimera.toml").is_err());
+        assert!(FileConfig::parse("port: 9000", "chimera.yaml").is_ok());
+        assert!(FileConfig::parse("{}", "chimera.json").is_err());
+    }
+
+    #[test]
+    fn resolves_paths_relative_to_the_config_file() {
+        assert_eq!(
+            resolve(Path::new("config"), "data.json"),
+            "config/data.json"
+        );
+        assert_eq!(
+            resolve(Path::new("config"), "/tmp/data.json"),
+            "/tmp/data.json"
+        );
+        assert_eq!(resolve(Path::new(""), "data.json"), "data.json");
+    }
+}
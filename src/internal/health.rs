@@ -0,0 +1,155 @@
+use crate::internal::chimera::CHIMERA_LATEST_VERSION;
+use crate::internal::reload::SharedReloadStatus;
+use crate::internal::routes::{current_routes, SharedRoutes};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use tokio::time::{timeout, Duration};
+
+pub const HEALTH_PATH: &str = "/__chimera/health";
+pub const READY_PATH: &str = "/__chimera/ready";
+// Same wait the data handlers give the lock before answering busy
+const READY_LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What the liveness and readiness probes report about the running server.
+pub struct Health {
+    pub started_at: Instant,
+    pub mode: String,
+    pub source: String,
+    pub watch: bool,
+    pub json_value: Arc<RwLock<Value>>,
+    pub routes: SharedRoutes,
+    pub last_reload: SharedReloadStatus,
+}
+
+impl Health {
+    fn report(&self, status: &str, data: Option<&Value>) -> Value {
+        // Collections in the dataset plus routes that only exist as definitions
+        let route_count = data.map(|data| {
+            let mut routes: BTreeSet<String> = data
+                .as_object()
+                .map(|collections| collections.keys().cloned().collect())
+                .unwrap_or_default();
+            routes.extend(current_routes(&self.routes).keys().map(str::to_string));
+            routes.len()
+        });
+        let last_reload = self
+            .last_reload
+            .read()
+            .map(|last_reload| json!(*last_reload))
+            .unwrap_or(Value::Null);
+
+        json!({
+            "status": status,
+            "version": CHIMERA_LATEST_VERSION,
+            "uptime_seconds": self.started_at.elapsed().as_secs(),
+            "mode": self.mode,
+            "source": self.source,
+            "routes": route_count,
+            "watch": self.watch,
+            "last_reload": last_reload,
+        })
+    }
+}
+
+/// `/__chimera/health` and `/__chimera/ready`, for whichever mode is serving.
+pub fn health_router<S>(health: Arc<Health>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(HEALTH_PATH, get(health_check))
+        .route(READY_PATH, get(ready_check))
+        .with_state(health)
+}
+
+/// `GET /__chimera/health`: the process is up and answering. Never waits on the
+/// dataset; `routes` is null while it is being written.
+async fn health_check(State(health): State<Arc<Health>>) -> Json<Value> {
+    let data = health.json_value.try_read().ok();
+    Json(health.report("ok", data.as_deref()))
+}
+
+/// `GET /__chimera/ready`: the dataset can be read without waiting. A failed
+/// reload doesn't make the server unready, as the previous data keeps serving.
+async fn ready_check(State(health): State<Arc<Health>>) -> Response {
+    match timeout(READY_LOCK_TIMEOUT, health.json_value.read()).await {
+        Ok(data) => Json(health.report("ready", Some(&data))).into_response(),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(health.report("busy", None)),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::reload::ReloadStatus;
+    use crate::internal::routes::{shared_routes, RouteTable};
+
+    fn health(data: Value, routes: Value) -> Arc<Health> {
+        Arc::new(Health {
+            started_at: Instant::now(),
+            mode: "http".to_string(),
+            source: "data.json".to_string(),
+            watch: true,
+            json_value: Arc::new(RwLock::new(data)),
+            routes: shared_routes(RouteTable::new(serde_json::from_value(routes).unwrap())),
+            last_reload: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn counts_collections_and_defined_routes_once() {
+        let health = health(
+            json!({"users": [], "posts": []}),
+            json!({"users": {"status": 200}, "ping": {"body": "pong"}}),
+        );
+        let Json(report) = health_check(State(health)).await;
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["routes"], 3);
+        assert_eq!(report["mode"], "http");
+        assert_eq!(report["last_reload"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn reports_the_last_reload() {
+        let health = health(json!({}), json!({}));
+        *health.last_reload.write().unwrap() = Some(ReloadStatus {
+            ok: false,
+            at: "2026-01-01T00:00:00Z".to_string(),
+            error: Some("bad json".to_string()),
+        });
+        let Json(report) = health_check(State(health.clone())).await;
+        assert_eq!(
+            report["last_reload"],
+            json!({"ok": false, "at": "2026-01-01T00:00:00Z", "error": "bad json"})
+        );
+        let response = ready_check(State(health)).await;
+        // A failed reload keeps serving the previous data
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn busy_while_the_dataset_is_written() {
+        let health = health(json!({"users": []}), json!({}));
+        let _writing = health.json_value.write().await;
+
+        let Json(report) = health_check(State(health.clone())).await;
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["routes"], Value::Null);
+        let response = ready_check(State(health.clone())).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -1,10 +1,16 @@
+use crate::internal::metrics::LockTimeout;
+use crate::internal::reload::SharedOrigins;
+use crate::internal::routes::RouteTable;
 use axum::{
-    http::StatusCode,
+    http::{Method, StatusCode},
     response::{IntoResponse, Response},
 };
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use rayon::prelude::*;
-use serde_json::Value;
-use tracing::warn;
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{info, warn};
 
 pub async fn shutdown_signal() {
     // Create a future that resolves when Ctrl+C is pressed
@@ -39,28 +45,135 @@ pub async fn shutdown_signal() {
     }
 }
 
+// Date formats recognised when ordering string fields chronologically
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d-%m-%Y", "%Y/%m/%d", "%d/%m/%Y"];
+
+fn parse_date(raw: &str) -> Option<NaiveDateTime> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.naive_utc());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S") {
+        return Some(dt);
+    }
+    DATE_FORMATS.iter().find_map(|fmt| {
+        NaiveDate::parse_from_str(raw, fmt)
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    })
+}
+
+// Rank used to order values of different JSON types against each other
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
 // Helper function for value comparison
-pub fn compare_values(a: &Value, b: &Value, key: &str, order: &str) -> std::cmp::Ordering {
-    let a_val = a.get(key).and_then(Value::as_i64).unwrap_or(0);
-    let b_val = b.get(key).and_then(Value::as_i64).unwrap_or(0);
-    if order == "asc" {
-        a_val.cmp(&b_val)
-    } else {
-        b_val.cmp(&a_val)
+// Numbers compare numerically, booleans false < true. Dates sort chronologically
+// and before other strings, which compare lexically (optionally case-folded), so
+// a column mixing the two still has a total order.
+pub fn compare_values(a: &Value, b: &Value, ignore_case: bool) -> Ordering {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_i64(), y.as_i64()) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => x
+                .as_f64()
+                .unwrap_or(0.0)
+                .total_cmp(&y.as_f64().unwrap_or(0.0)),
+        },
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::String(x), Value::String(y)) => match (parse_date(x), parse_date(y)) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            _ if ignore_case => x.to_lowercase().cmp(&y.to_lowercase()),
+            _ => x.cmp(y),
+        },
+        _ => type_rank(a).cmp(&type_rank(b)),
     }
 }
 
+// CORS layer shared by the HTTP and WebSocket servers. Allowed origins are read
+// on every request so edits to chimera.cors apply without a restart.
+pub fn cors_layer(cors_enabled: bool, allowed_origins: SharedOrigins) -> CorsLayer {
+    if !cors_enabled {
+        info!("CORS: *");
+        return CorsLayer::new()
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers(Any)
+            .allow_origin(Any);
+    }
+
+    let restricted = allowed_origins
+        .read()
+        .map(|origins| !origins.is_empty())
+        .unwrap_or(false);
+    info!("CORS: {}", if restricted { "chimera.cors" } else { "*" });
+
+    CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers(Any)
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            allowed_origins.read().is_ok_and(|origins| {
+                origins.is_empty()
+                    || origins
+                        .iter()
+                        .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+            })
+        }))
+        .allow_credentials(false)
+}
+
+// JSON error body, e.g. `{"status": 404, "error": "Not Found", "message": "..."}`
+pub fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        axum::Json(json!({
+            "status": status.as_u16(),
+            "error": status.canonical_reason().unwrap_or("Error"),
+            "message": message.into(),
+        })),
+    )
+        .into_response()
+}
+
 // Helper function for busy response
 pub fn server_busy_response() -> Response {
     warn!("Server busy response returned");
-    (
+    let mut response = (
         StatusCode::INTERNAL_SERVER_ERROR,
         "Server is busy, please try again later",
     )
-        .into_response()
+        .into_response();
+    // Counted as a lock-wait timeout by `/metrics`
+    response.extensions_mut().insert(LockTimeout);
+    response
 }
 
-pub fn find_key_and_id_lengths(parsed_content: &Value) -> Option<(usize, usize)> {
+pub fn find_key_and_id_lengths(
+    parsed_content: &Value,
+    default_id_field: &str,
+    routes: &RouteTable,
+) -> Option<(usize, usize)> {
     let object = parsed_content.as_object()?;
 
     object
@@ -69,23 +182,79 @@ pub fn find_key_and_id_lengths(parsed_content: &Value) -> Option<(usize, usize)>
         .par_iter()
         .filter_map(|(key, value)| {
             let arr = value.as_array()?;
+            let id_field = routes
+                .get(key)
+                .and_then(|config| config.id_field.as_deref())
+                .unwrap_or(default_id_field);
 
-            // All items must be objects with numeric "id"
-            if arr
-                .iter()
-                .all(|v| v.get("id").and_then(Value::as_i64).is_some())
-            {
-                let max_id_len = arr
-                    .par_iter()
-                    .filter_map(|v| v.get("id")?.as_i64())
-                    .map(|id| id.abs().to_string().len())
-                    .max()?;
-
-                let key_len = key.len();
-                Some((key_len, max_id_len))
-            } else {
-                None
-            }
+            // All items must be objects with a numeric or string id
+            let id_lengths = arr
+                .par_iter()
+                .map(|v| match v.get(id_field)? {
+                    Value::Number(n) => Some(n.to_string().trim_start_matches('-').len()),
+                    Value::String(s) => Some(s.len()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            let max_id_len = id_lengths.into_iter().max()?;
+            let key_len = key.len();
+            Some((key_len, max_id_len))
         })
         .max_by_key(|(key_len, id_len)| key_len + id_len)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compares_dates_chronologically() {
+        let earlier = json!("02/01/2024");
+        let later = json!("2024-01-03");
+        assert_eq!(compare_values(&earlier, &later, false), Ordering::Less);
+    }
+
+    #[test]
+    fn mixed_date_and_text_column_has_a_total_order() {
+        let mut values = vec![
+            json!("zebra"),
+            json!("2024-01-02"),
+            json!("Apple"),
+            json!("2023-12-31"),
+            json!("2024-1-1"),
+        ];
+        values.sort_by(|a, b| compare_values(a, b, false));
+        assert_eq!(
+            values,
+            vec![
+                json!("2023-12-31"),
+                json!("2024-1-1"),
+                json!("2024-01-02"),
+                json!("Apple"),
+                json!("zebra"),
+            ]
+        );
+        for a in &values {
+            for b in &values {
+                assert_eq!(
+                    compare_values(a, b, false),
+                    compare_values(b, a, false).reverse()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn orders_values_of_different_types_by_type() {
+        assert_eq!(
+            compare_values(&json!(null), &json!(1), false),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_values(&json!("1"), &json!(2), false),
+            Ordering::Greater
+        );
+    }
+}
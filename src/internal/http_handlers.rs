@@ -1,8 +1,18 @@
-use crate::internal::chimera::{AppState, CHIMERA_LATEST_VERSION};
-use crate::internal::helpers::{compare_values, server_busy_response};
+use crate::internal::chimera::AppState;
+use crate::internal::helpers::{error_response, server_busy_response};
+use crate::internal::query::{
+    apply_filters, apply_search, envelope_requested, link_header, list_param, page_window,
+    sort_records, sort_spec, Projection,
+};
+use crate::internal::records::{
+    assign_ids, id_value, matches_id, record_id, set_id, split_record_path,
+};
+use crate::internal::relations::{embed, expand, related_collections, NestedRoute};
+use crate::internal::routes::ROUTES_KEY;
+use crate::internal::session::Dataset;
 use axum::{
-    extract::{Path, State},
-    http::{StatusCode, Uri},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
     response::{IntoResponse, Response},
     Form, Json,
 };
@@ -12,7 +22,7 @@ use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::time::{sleep, timeout, Duration};
+use tokio::time::{timeout, Duration};
 use tracing::{info, warn};
 
 #[derive(Deserialize)]
@@ -21,22 +31,42 @@ pub struct FormData {
     fields: HashMap<String, String>,
 }
 
-pub async fn ping_pong() -> impl IntoResponse {
-    (
-        StatusCode::OK,
-        [("content-type", "text/plain")],
-        format!(
-            "status: ONLINE\nversion: {}\n🐲 All systems fused and breathing fire.",
-            CHIMERA_LATEST_VERSION
-        )
-        .to_string(),
+/// `/` serves nothing of its own; `route_overrides` answers first when the data
+/// file has a `$routes` entry for it.
+pub async fn mock_root() -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        format!("Nothing is mocked at `/`. Define it under `{}`", ROUTES_KEY),
     )
 }
 
+// Apply `_embed` / `_expand` to the records about to be returned
+fn join_related(
+    state: &AppState,
+    records: &mut Value,
+    related: &Value,
+    collection: &str,
+    embeds: &[String],
+    expands: &[String],
+) {
+    if !embeds.is_empty() {
+        let id_field = state.id_field_for(collection);
+        embed(records, related, collection, &id_field, embeds);
+    }
+    if !expands.is_empty() {
+        expand(records, related, expands, |parent| {
+            state.id_field_for(parent)
+        });
+    }
+}
+
 pub async fn get_data(
     Path(route): Path<String>,
     State(state): State<Arc<AppState>>,
+    dataset: Dataset,
     uri: Uri,
+    headers: HeaderMap,
+    Query(params): Query<Vec<(String, String)>>,
 ) -> Response {
     let start_time = Instant::now();
 
@@ -46,25 +76,44 @@ pub async fn get_data(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for(&route, "GET").wait().await;
+
+    let embeds = list_param(&params, "_embed");
+    let expands = list_param(&params, "_expand");
+    let projection = Projection::from_params(&params);
 
     // Clone only the needed data immediately after acquiring lock
+    let mut record_id = None;
+    let mut collection = route.clone();
+    let related;
     let route_data = {
-        let json_data = match timeout(Duration::from_millis(100), state.json_value.read()).await {
+        let json_data = match timeout(Duration::from_millis(100), dataset.json_value.read()).await {
             Ok(lock) => {
-                if let Some(path_id) = route.split("/").last() {
-                    if let Ok(_id) = path_id.parse::<usize>() {
-                        let mut route_parts: Vec<&str> = route.split('/').collect();
-                        route_parts.pop();
-                        let base_path = route_parts.join("/");
-                        lock.get(&base_path).cloned()
-                    } else {
-                        lock.get(&route).cloned()
+                related = related_collections(&lock, &embeds, &expands);
+                // `posts/1/comments` serves the comments whose `postId` is 1
+                let nested = |route: &str| {
+                    NestedRoute::parse(&lock, route)
+                        .map(|nested| (nested.child.to_string(), nested.children(&lock)))
+                };
+                match split_record_path(&lock, &route) {
+                    Some((base_path, id)) => {
+                        record_id = Some(id.to_string());
+                        collection = base_path.to_string();
+                        match lock.get(base_path) {
+                            Some(value) => Some(value.clone()),
+                            None => nested(base_path).map(|(child, children)| {
+                                collection = child;
+                                children
+                            }),
+                        }
                     }
-                } else {
-                    lock.get(&route).cloned()
+                    None => match lock.get(&route) {
+                        Some(value) => Some(value.clone()),
+                        None => nested(&route).map(|(child, children)| {
+                            collection = child;
+                            children
+                        }),
+                    },
                 }
             }
             Err(_) => {
@@ -89,49 +138,128 @@ pub async fn get_data(
 
     match route_data {
         Some(mut value) => {
-            if let Some(path_id) = route.split("/").last() {
-                if let Ok(_id) = path_id.parse::<usize>() {
-                    if let Value::Array(ref mut arr) = value {
-                        arr.retain(|obj| {
-                            obj.get("id")
-                                .and_then(|id| id.as_u64())
-                                .map_or(false, |id_num| id_num == _id as u64)
-                        });
+            if let Some(id) = &record_id {
+                let id_field = state.id_field_for(&collection);
+                if let Value::Array(ref mut arr) = value {
+                    arr.retain(|obj| matches_id(obj, &id_field, id));
+                }
+                let record = match &mut value {
+                    Value::Array(arr) if !arr.is_empty() => Some(arr.swap_remove(0)),
+                    _ => None,
+                };
+
+                let elapsed = start_time.elapsed().as_millis();
+                if record.is_none() && !state.single_as_array {
+                    let message = format!("No record found with id {}", id);
+                    if !state.logs_disabled {
+                        warn!(
+                            date_time = date_time,
+                            status = "404",
+                            method = "GET",
+                            path = requested_path,
+                            error = message,
+                            elapsed_ms = elapsed,
+                            records = 0,
+                            "HTTP request"
+                        );
                     }
+                    return error_response(StatusCode::NOT_FOUND, message);
+                }
+
+                if !state.logs_disabled {
+                    info!(
+                        date_time = date_time,
+                        status = "200",
+                        method = "GET",
+                        path = requested_path,
+                        elapsed_ms = elapsed,
+                        records = usize::from(record.is_some()),
+                        "HTTP request"
+                    );
+                }
+                let record = record.map(|mut record| {
+                    join_related(
+                        &state,
+                        &mut record,
+                        &related,
+                        &collection,
+                        &embeds,
+                        &expands,
+                    );
+                    if let Some(projection) = &projection {
+                        projection.apply(&mut record);
+                    }
+                    record
+                });
+                // `--single-as-array` keeps the old `[record]` / `[]` responses
+                let body = match record {
+                    Some(record) if !state.single_as_array => record,
+                    record => Value::Array(record.into_iter().collect()),
+                };
+                return (StatusCode::OK, axum::Json(body)).into_response();
+            }
+
+            // Rest of processing happens WITHOUT holding the lock
+            if let Value::Array(arr) = &mut value {
+                if let Err(message) = apply_filters(arr, &params) {
                     let elapsed = start_time.elapsed().as_millis();
                     if !state.logs_disabled {
-                        info!(
+                        warn!(
                             date_time = date_time,
-                            status = "200",
+                            status = "400",
                             method = "GET",
                             path = requested_path,
+                            error = message,
                             elapsed_ms = elapsed,
-                            records = value.as_array().map_or(0, |arr| arr.len()),
+                            records = 0,
                             "HTTP request"
                         );
                     }
-                    return (StatusCode::OK, axum::Json(value)).into_response();
+                    return error_response(StatusCode::BAD_REQUEST, message);
                 }
+                apply_search(arr, &params, &state.search_fields_for(&collection));
             }
 
-            // Rest of processing happens WITHOUT holding the lock
-            if let Some((order, key)) = state.sort_rules.get(&route) {
+            let sort_rule = state.sort_rule_for(&collection);
+            if let Some(spec) = sort_spec(&params, sort_rule.as_ref(), state.nulls) {
                 if let Value::Array(arr) = &mut value {
-                    if arr.len() > 1 {
-                        use rayon::slice::ParallelSliceMut;
-                        arr.par_sort_by(|a, b| compare_values(a, b, key, order));
-                    }
+                    sort_records(arr, &spec);
                 }
             }
 
-            if state.paginate > 0 {
-                if let Value::Array(arr) = &value {
-                    if arr.len() > state.paginate as usize {
-                        value = Value::Array(arr[..state.paginate as usize].to_vec());
+            let mut window = None;
+            if let Value::Array(arr) = &mut value {
+                match page_window(&params, state.page_size_for(&collection), arr.len()) {
+                    Ok(Some(w)) => {
+                        arr.truncate(w.end);
+                        arr.drain(..w.start);
+                        window = Some(w);
+                    }
+                    Ok(None) => {}
+                    Err(message) => {
+                        let elapsed = start_time.elapsed().as_millis();
+                        if !state.logs_disabled {
+                            warn!(
+                                date_time = date_time,
+                                status = "400",
+                                method = "GET",
+                                path = requested_path,
+                                error = message,
+                                elapsed_ms = elapsed,
+                                records = 0,
+                                "HTTP request"
+                            );
+                        }
+                        return error_response(StatusCode::BAD_REQUEST, message);
                     }
                 }
             }
 
+            join_related(&state, &mut value, &related, &collection, &embeds, &expands);
+            if let Some(projection) = &projection {
+                projection.apply(&mut value);
+            }
+
             let elapsed = start_time.elapsed().as_millis();
             if !state.logs_disabled {
                 info!(
@@ -144,7 +272,32 @@ pub async fn get_data(
                     "HTTP request"
                 );
             }
-            (StatusCode::OK, axum::Json(value)).into_response()
+
+            let Some(window) = window else {
+                return (StatusCode::OK, axum::Json(value)).into_response();
+            };
+
+            let body = if envelope_requested(&params, state.envelope) {
+                json!({ "data": value, "meta": window })
+            } else {
+                value
+            };
+            let mut response = (StatusCode::OK, axum::Json(body)).into_response();
+            response
+                .headers_mut()
+                .insert("x-total-count", HeaderValue::from(window.total));
+
+            let host = headers
+                .get(header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("localhost");
+            let base_url = format!("http://{}{}", host, requested_path);
+            if let Some(link) = link_header(&base_url, &params, &window)
+                .and_then(|link| HeaderValue::from_str(&link).ok())
+            {
+                response.headers_mut().insert(header::LINK, link);
+            }
+            response
         }
         None => {
             let elapsed = start_time.elapsed().as_millis();
@@ -168,6 +321,7 @@ pub async fn get_data(
 pub async fn delete_data(
     Path(route): Path<String>,
     State(state): State<Arc<AppState>>,
+    dataset: Dataset,
     uri: Uri,
 ) -> Response {
     let start_time = Instant::now();
@@ -178,14 +332,12 @@ pub async fn delete_data(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for(&route, "DELETE").wait().await;
 
     // Handle the DELETE operation
     let delete_result = {
         let mut json_data =
-            match timeout(Duration::from_millis(100), state.json_value.write()).await {
+            match timeout(Duration::from_millis(100), dataset.json_value.write()).await {
                 Ok(lock) => lock,
                 Err(_) => {
                     let elapsed = start_time.elapsed().as_millis();
@@ -206,51 +358,26 @@ pub async fn delete_data(
             };
 
         // Check if we're deleting a specific ID
-        if let Some(path_id) = route.split("/").last() {
-            if let Ok(id) = path_id.parse::<usize>() {
-                // Extract base path (remove the ID part)
-                let mut route_parts: Vec<&str> = route.split('/').collect();
-                route_parts.pop();
-                let base_path = route_parts.join("/");
-
-                match json_data.get_mut(&base_path) {
-                    Some(Value::Array(arr)) => {
-                        let original_len = arr.len();
-                        arr.retain(|obj| {
-                            obj.get("id")
-                                .and_then(|id_val| id_val.as_u64())
-                                .map_or(true, |id_num| id_num != id as u64)
-                        });
-                        let deleted_count = original_len - arr.len();
-
-                        if deleted_count > 0 {
-                            (
-                                "200",
-                                format!("Deleted {} record(s) with id {}", deleted_count, id),
-                                deleted_count,
-                            )
-                        } else {
-                            ("404", format!("No record found with id {}", id), 0)
-                        }
-                    }
-                    Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
-                    None => ("404", "Route not registered !!".to_string(), 0),
-                }
-            } else {
-                // Delete entire collection
-                match json_data.get_mut(&route) {
-                    Some(Value::Array(arr)) => {
-                        let deleted_count = arr.len();
-                        arr.clear();
+        if let Some((base_path, id)) = split_record_path(&json_data, &route) {
+            let id_field = state.id_field_for(base_path);
+            match json_data.get_mut(base_path) {
+                Some(Value::Array(arr)) => {
+                    let original_len = arr.len();
+                    arr.retain(|obj| !matches_id(obj, &id_field, id));
+                    let deleted_count = original_len - arr.len();
+
+                    if deleted_count > 0 {
                         (
                             "200",
-                            "All records deleted successfully".to_string(),
+                            format!("Deleted {} record(s) with id {}", deleted_count, id),
                             deleted_count,
                         )
+                    } else {
+                        ("404", format!("No record found with id {}", id), 0)
                     }
-                    Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
-                    None => ("404", "Route not registered !!".to_string(), 0),
                 }
+                Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
+                None => ("404", "Route not registered !!".to_string(), 0),
             }
         } else {
             // Delete entire collection
@@ -273,6 +400,10 @@ pub async fn delete_data(
     let elapsed = start_time.elapsed().as_millis();
     let (status_code, message, affected_records) = delete_result;
 
+    if matches!(status_code, "200" | "201") {
+        dataset.mark_dirty(&state);
+    }
+
     if !state.logs_disabled {
         match status_code {
             "200" | "201" => info!(
@@ -309,6 +440,7 @@ pub async fn delete_data(
 pub async fn post_data(
     Path(route): Path<String>,
     State(state): State<Arc<AppState>>,
+    dataset: Dataset,
     uri: Uri,
     Json(payload): Json<Value>,
 ) -> Response {
@@ -320,14 +452,13 @@ pub async fn post_data(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for(&route, "POST").wait().await;
 
     // Handle the POST operation
+    let mut target = route.clone();
     let post_result = {
         let mut json_data =
-            match timeout(Duration::from_millis(100), state.json_value.write()).await {
+            match timeout(Duration::from_millis(100), dataset.json_value.write()).await {
                 Ok(lock) => lock,
                 Err(_) => {
                     let elapsed = start_time.elapsed().as_millis();
@@ -347,50 +478,81 @@ pub async fn post_data(
                 }
             };
 
+        // `POST /posts/1/comments` adds to `comments` with `postId` set to 1
+        let link = NestedRoute::parse(&json_data, &route).map(|nested| {
+            target = nested.child.to_string();
+            let parent_key = nested.parent_key(&json_data, &state.id_field_for(nested.parent));
+            (nested.foreign_key(&json_data), parent_key)
+        });
+
         if let Value::Object(ref mut obj) = *json_data {
-            match obj.get_mut(&route) {
-                Some(Value::Array(arr)) => match payload {
-                    Value::Array(new_items) => {
-                        let added_count = new_items.len();
-                        arr.extend(new_items);
-                        (
-                            "201",
-                            format!("Added {} record(s) successfully", added_count),
-                            added_count,
-                        )
-                    }
-                    single_item => {
-                        arr.push(single_item);
-                        ("201", "Record added successfully".to_string(), 1)
+            let (mut items, batch) = match payload {
+                Value::Array(items) => (items, true),
+                single_item => (vec![single_item], false),
+            };
+            if let Some((foreign_key, parent_key)) = &link {
+                for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                    item.insert(foreign_key.clone(), parent_key.clone());
+                }
+            }
+            let created_route = !obj.contains_key(&target);
+            let id_field = state.id_field_for(&target);
+            let arr = match obj
+                .entry(target.clone())
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(arr) => Some(arr),
+                _ => None,
+            };
+
+            match arr.map(|arr| (assign_ids(arr, items, &id_field), arr)) {
+                None => (
+                    "400",
+                    "Route exists but is not an array.".to_string(),
+                    0,
+                    None,
+                ),
+                Some((Err(id), arr)) => {
+                    if created_route && arr.is_empty() {
+                        obj.remove(&target);
                     }
-                },
-                Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
-                None => {
-                    // Create new route with the payload
-                    match payload {
-                        Value::Array(items) => {
-                            let added_count = items.len();
-                            obj.insert(route.clone(), Value::Array(items));
-                            (
-                                "201",
-                                format!("Created route and added {} record(s)", added_count),
-                                added_count,
-                            )
-                        }
-                        single_item => {
-                            obj.insert(route.clone(), Value::Array(vec![single_item]));
-                            ("201", "Created route and added record".to_string(), 1)
+                    (
+                        "409",
+                        format!("A record with id {} already exists", id),
+                        0,
+                        None,
+                    )
+                }
+                Some((Ok(items), arr)) => {
+                    let added_count = items.len();
+                    arr.extend(items.iter().cloned());
+                    let message = match (created_route, batch) {
+                        (true, true) => {
+                            format!("Created route and added {} record(s)", added_count)
                         }
-                    }
+                        (true, false) => "Created route and added record".to_string(),
+                        (false, true) => format!("Added {} record(s) successfully", added_count),
+                        (false, false) => "Record added successfully".to_string(),
+                    };
+                    let created = if batch {
+                        Value::Array(items)
+                    } else {
+                        items.into_iter().next().unwrap_or(Value::Null)
+                    };
+                    ("201", message, added_count, Some(created))
                 }
             }
         } else {
-            ("500", "Root JSON is not an object".to_string(), 0)
+            ("500", "Root JSON is not an object".to_string(), 0, None)
         }
     };
 
     let elapsed = start_time.elapsed().as_millis();
-    let (status_code, message, affected_records) = post_result;
+    let (status_code, message, affected_records, created) = post_result;
+
+    if matches!(status_code, "200" | "201") {
+        dataset.mark_dirty(&state);
+    }
 
     if !state.logs_disabled {
         match status_code {
@@ -416,10 +578,19 @@ pub async fn post_data(
         }
     }
 
-    match status_code {
-        "201" => (StatusCode::CREATED, message).into_response(),
-        "400" => (StatusCode::BAD_REQUEST, message).into_response(),
-        "500" => (StatusCode::INTERNAL_SERVER_ERROR, message).into_response(),
+    match (status_code, created) {
+        ("201", Some(created)) => {
+            // Batches have no single resource to point at
+            let location = record_id(&created, &state.id_field_for(&target))
+                .and_then(|id| HeaderValue::from_str(&format!("/{}/{}", target, id)).ok());
+            let mut response = (StatusCode::CREATED, Json(created)).into_response();
+            if let Some(location) = location {
+                response.headers_mut().insert(header::LOCATION, location);
+            }
+            response
+        }
+        ("400", _) => (StatusCode::BAD_REQUEST, message).into_response(),
+        ("409", _) => (StatusCode::CONFLICT, message).into_response(),
         _ => (StatusCode::INTERNAL_SERVER_ERROR, message).into_response(),
     }
 }
@@ -427,6 +598,7 @@ pub async fn post_data(
 pub async fn put_data(
     Path(route): Path<String>,
     State(state): State<Arc<AppState>>,
+    dataset: Dataset,
     uri: Uri,
     Json(payload): Json<Value>,
 ) -> Response {
@@ -438,14 +610,12 @@ pub async fn put_data(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for(&route, "PUT").wait().await;
 
     // Handle the PUT operation
     let put_result = {
         let mut json_data =
-            match timeout(Duration::from_millis(100), state.json_value.write()).await {
+            match timeout(Duration::from_millis(100), dataset.json_value.write()).await {
                 Ok(lock) => lock,
                 Err(_) => {
                     let elapsed = start_time.elapsed().as_millis();
@@ -465,75 +635,33 @@ pub async fn put_data(
                 }
             };
 
+        let record_path = split_record_path(&json_data, &route);
         if let Value::Object(ref mut obj) = *json_data {
             // Check if we're updating a specific ID
-            if let Some(path_id) = route.split("/").last() {
-                if let Ok(id) = path_id.parse::<usize>() {
-                    // Extract base path (remove the ID part)
-                    let mut route_parts: Vec<&str> = route.split('/').collect();
-                    route_parts.pop();
-                    let base_path = route_parts.join("/");
-
-                    match obj.get_mut(&base_path) {
-                        Some(Value::Array(arr)) => {
-                            let mut found = false;
-                            for item in arr.iter_mut() {
-                                if let Some(item_id) = item.get("id").and_then(|id| id.as_u64()) {
-                                    if item_id == id as u64 {
-                                        *item = payload.clone();
-                                        // Ensure the ID is preserved
-                                        if let Value::Object(ref mut item_obj) = item {
-                                            item_obj.insert(
-                                                "id".to_string(),
-                                                Value::Number(serde_json::Number::from(id)),
-                                            );
-                                        }
-                                        found = true;
-                                        break;
-                                    }
-                                }
-                            }
-
-                            if found {
+            if let Some((base_path, id)) = record_path {
+                let id_field = state.id_field_for(base_path);
+                match obj.get_mut(base_path) {
+                    Some(Value::Array(arr)) => {
+                        let id_json = id_value(arr, &id_field, id);
+                        match arr.iter_mut().find(|item| matches_id(item, &id_field, id)) {
+                            Some(item) => {
+                                // Keep the stored id, whatever the payload says
+                                let stored_id = item.get(&id_field).cloned().unwrap_or(id_json);
+                                *item = payload;
+                                set_id(item, &id_field, stored_id);
                                 ("200", format!("Updated record with id {}", id), 1)
-                            } else {
+                            }
+                            None => {
                                 // Create new record with the specified ID
-                                let mut new_item = payload.clone();
-                                if let Value::Object(ref mut item_obj) = new_item {
-                                    item_obj.insert(
-                                        "id".to_string(),
-                                        Value::Number(serde_json::Number::from(id)),
-                                    );
-                                }
+                                let mut new_item = payload;
+                                set_id(&mut new_item, &id_field, id_json);
                                 arr.push(new_item);
                                 ("201", format!("Created record with id {}", id), 1)
                             }
                         }
-                        Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
-                        None => ("404", "Route not registered !!".to_string(), 0),
-                    }
-                } else {
-                    // Replace entire collection
-                    let record_count = match &payload {
-                        Value::Array(arr) => arr.len(),
-                        _ => 1,
-                    };
-                    let was_existing = obj.contains_key(&route);
-                    obj.insert(route.clone(), payload);
-
-                    if was_existing {
-                        (
-                            "200",
-                            format!("Replaced entire collection with {} record(s)", record_count),
-                            record_count,
-                        )
-                    } else {
-                        (
-                            "201",
-                            format!("Created collection with {} record(s)", record_count),
-                            record_count,
-                        )
                     }
+                    Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
+                    None => ("404", "Route not registered !!".to_string(), 0),
                 }
             } else {
                 // Replace entire collection
@@ -566,6 +694,10 @@ pub async fn put_data(
     let elapsed = start_time.elapsed().as_millis();
     let (status_code, message, affected_records) = put_result;
 
+    if matches!(status_code, "200" | "201") {
+        dataset.mark_dirty(&state);
+    }
+
     if !state.logs_disabled {
         match status_code {
             "200" | "201" => info!(
@@ -603,6 +735,7 @@ pub async fn put_data(
 pub async fn patch_data(
     Path(route): Path<String>,
     State(state): State<Arc<AppState>>,
+    dataset: Dataset,
     uri: Uri,
     Json(payload): Json<Value>,
 ) -> Response {
@@ -614,14 +747,12 @@ pub async fn patch_data(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for(&route, "PATCH").wait().await;
 
     // Handle the PATCH operation
     let patch_result = {
         let mut json_data =
-            match timeout(Duration::from_millis(100), state.json_value.write()).await {
+            match timeout(Duration::from_millis(100), dataset.json_value.write()).await {
                 Ok(lock) => lock,
                 Err(_) => {
                     let elapsed = start_time.elapsed().as_millis();
@@ -641,50 +772,36 @@ pub async fn patch_data(
                 }
             };
 
+        let record_path = split_record_path(&json_data, &route);
         if let Value::Object(ref mut obj) = *json_data {
             // Check if we're updating a specific ID
-            if let Some(path_id) = route.split("/").last() {
-                if let Ok(id) = path_id.parse::<usize>() {
-                    // Extract base path (remove the ID part)
-                    let mut route_parts: Vec<&str> = route.split('/').collect();
-                    route_parts.pop();
-                    let base_path = route_parts.join("/");
-
-                    match obj.get_mut(&base_path) {
-                        Some(Value::Array(arr)) => {
-                            let mut found = false;
-                            for item in arr.iter_mut() {
-                                if let Some(item_id) = item.get("id").and_then(|id| id.as_u64()) {
-                                    if item_id == id as u64 {
-                                        // Merge the payload into the existing item
-                                        if let (Value::Object(existing), Value::Object(updates)) =
-                                            (item, &payload)
-                                        {
-                                            for (key, value) in updates {
-                                                existing.insert(key.clone(), value.clone());
-                                            }
-                                            found = true;
-                                            break;
-                                        }
-                                    }
+            if let Some((base_path, id)) = record_path {
+                let id_field = state.id_field_for(base_path);
+                match obj.get_mut(base_path) {
+                    Some(Value::Array(arr)) => {
+                        let mut found = false;
+                        if let Some(item) =
+                            arr.iter_mut().find(|item| matches_id(item, &id_field, id))
+                        {
+                            // Merge the payload into the existing item
+                            if let (Value::Object(existing), Value::Object(updates)) =
+                                (item, &payload)
+                            {
+                                for (key, value) in updates {
+                                    existing.insert(key.clone(), value.clone());
                                 }
+                                found = true;
                             }
+                        }
 
-                            if found {
-                                ("200", format!("Partially updated record with id {}", id), 1)
-                            } else {
-                                ("404", format!("No record found with id {}", id), 0)
-                            }
+                        if found {
+                            ("200", format!("Partially updated record with id {}", id), 1)
+                        } else {
+                            ("404", format!("No record found with id {}", id), 0)
                         }
-                        Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
-                        None => ("404", "Route not registered !!".to_string(), 0),
                     }
-                } else {
-                    (
-                        "400",
-                        "PATCH requires a specific resource ID".to_string(),
-                        0,
-                    )
+                    Some(_) => ("400", "Route exists but is not an array.".to_string(), 0),
+                    None => ("404", "Route not registered !!".to_string(), 0),
                 }
             } else {
                 (
@@ -701,6 +818,10 @@ pub async fn patch_data(
     let elapsed = start_time.elapsed().as_millis();
     let (status_code, message, affected_records) = patch_result;
 
+    if matches!(status_code, "200" | "201") {
+        dataset.mark_dirty(&state);
+    }
+
     if !state.logs_disabled {
         match status_code {
             "200" => info!(
@@ -747,9 +868,7 @@ pub async fn handle_form_submission(
     let requested_path = uri.path();
 
     // Add the Latency
-    if state.latency > 0 {
-        sleep(Duration::from_millis(state.latency)).await;
-    }
+    state.latency_for("submit-form", "POST").wait().await;
 
     if form_data.fields.is_empty() {
         let elapsed = start_time.elapsed().as_millis();
@@ -789,7 +908,7 @@ pub async fn handle_form_submission(
             "HTTP request"
         );
     }
-    return (
+    (
         StatusCode::OK,
         axum::Json(json!(
             {
@@ -798,5 +917,137 @@ pub async fn handle_form_submission(
             }
         )),
     )
-        .into_response();
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::routes::RouteTable;
+    use axum::body::to_bytes;
+
+    fn state(data: Value, single_as_array: bool) -> Arc<AppState> {
+        Arc::new(AppState {
+            single_as_array,
+            ..AppState::for_tests(data)
+        })
+    }
+
+    async fn get(state: &Arc<AppState>, route: &str) -> (StatusCode, Value) {
+        get_with(state, route, &[]).await
+    }
+
+    async fn get_with(
+        state: &Arc<AppState>,
+        route: &str,
+        query: &[(&str, &str)],
+    ) -> (StatusCode, Value) {
+        let dataset = Dataset {
+            json_value: state.json_value.clone(),
+            session: None,
+        };
+        let uri: Uri = format!("/{}", route).parse().unwrap();
+        let response = get_data(
+            Path(route.to_string()),
+            State(state.clone()),
+            dataset,
+            uri,
+            HeaderMap::new(),
+            Query(
+                query
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+        )
+        .await;
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn single_records_are_objects() {
+        let state = state(json!({"users": [{"id": 1}, {"id": 2}]}), false);
+
+        assert_eq!(
+            get(&state, "users/2").await,
+            (StatusCode::OK, json!({"id": 2}))
+        );
+        let (status, body) = get(&state, "users/9").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["status"], 404);
+    }
+
+    #[tokio::test]
+    async fn single_as_array_keeps_the_old_shape() {
+        let state = state(json!({"users": [{"id": 1}]}), true);
+
+        assert_eq!(
+            get(&state, "users/1").await,
+            (StatusCode::OK, json!([{"id": 1}]))
+        );
+        assert_eq!(get(&state, "users/9").await, (StatusCode::OK, json!([])));
+    }
+
+    #[tokio::test]
+    async fn nested_records_use_the_child_id_field() {
+        let state = state(
+            json!({
+                "users": [{"id": 1}],
+                "posts": [{"slug": 10, "userId": 1}, {"slug": 11, "userId": 2}],
+            }),
+            false,
+        );
+        let routes = json!({"posts": {"id_field": "slug"}});
+        *state.routes.write().unwrap() =
+            Arc::new(RouteTable::new(serde_json::from_value(routes).unwrap()));
+
+        assert_eq!(
+            get(&state, "users/1/posts/10").await,
+            (StatusCode::OK, json!({"slug": 10, "userId": 1}))
+        );
+        assert_eq!(
+            get(&state, "users/1/posts/11").await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_queries_get_a_json_error() {
+        let state = state(json!({"users": [{"id": 1}]}), false);
+
+        for query in [[("name_like", "(")], [("_page", "0")]] {
+            let (status, body) = get_with(&state, "users", &query).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["status"], 400);
+        }
+    }
+
+    #[tokio::test]
+    async fn nested_routes_use_the_child_route_settings() {
+        let state = state(
+            json!({
+                "posts": [{"id": 1}],
+                "comments": [
+                    {"id": 1, "postId": 1, "body": "first", "author": "zed"},
+                    {"id": 2, "postId": 1, "body": "zed was here", "author": "amy"},
+                    {"id": 3, "postId": 2, "body": "zed", "author": "zed"},
+                ],
+            }),
+            false,
+        );
+        let routes = json!({"comments": {"search_fields": ["author"], "sort": "author"}});
+        *state.routes.write().unwrap() =
+            Arc::new(RouteTable::new(serde_json::from_value(routes).unwrap()));
+
+        let (_, found) = get_with(&state, "posts/1/comments", &[("q", "zed")]).await;
+        assert_eq!(
+            found,
+            json!([{"id": 1, "postId": 1, "body": "first", "author": "zed"}])
+        );
+
+        let (_, sorted) = get(&state, "posts/1/comments").await;
+        assert_eq!(sorted[0]["author"], "amy");
+    }
 }
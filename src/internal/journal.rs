@@ -0,0 +1,401 @@
+use crate::internal::admin::{ADMIN_PREFIX, REDACTED};
+use crate::internal::chimera::AppState;
+use crate::internal::helpers::error_response;
+use crate::internal::metrics::METRICS_PATH;
+use crate::internal::session::session_id;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{SecondsFormat, Utc};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::error;
+
+pub const JOURNAL_PATH: &str = "/__chimera/requests";
+const DEFAULT_JOURNAL_SIZE: usize = 1000;
+// Larger bodies are noted by size instead of being kept
+const MAX_JOURNAL_BODY_BYTES: usize = 64 * 1024;
+
+/// `[journal]` settings: how many requests to keep in memory, and an optional
+/// JSON Lines file every request is appended to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalConfig {
+    pub size: Option<usize>,
+    pub file: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub timestamp: String,
+    pub method: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    // `X-Chimera-Session`, so parallel test workers can pick out their own requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    // Parsed JSON when the body is JSON, text otherwise, null when empty
+    pub body: Value,
+    pub status: u16,
+    pub elapsed_ms: u128,
+}
+
+/// Ring buffer of the requests the server received, newest last.
+pub struct Journal {
+    entries: Mutex<VecDeque<JournalEntry>>,
+    capacity: usize,
+    next_id: AtomicU64,
+    file: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl Journal {
+    /// Start a journal; with a `file`, entries are also appended to it as JSON Lines.
+    pub fn spawn(config: &JournalConfig) -> Self {
+        let file = config.file.as_ref().map(|path| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(append_lines(PathBuf::from(path), rx));
+            tx
+        });
+        Journal {
+            entries: Mutex::new(VecDeque::new()),
+            capacity: config.size.unwrap_or(DEFAULT_JOURNAL_SIZE),
+            next_id: AtomicU64::new(1),
+            file,
+        }
+    }
+
+    fn record(&self, mut entry: JournalEntry) {
+        entry.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Some(file) = &self.file {
+            if let Ok(line) = serde_json::to_string(&entry) {
+                let _ = file.send(line);
+            }
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+async fn append_lines(path: PathBuf, mut lines: mpsc::UnboundedReceiver<String>) {
+    let mut file = match tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open journal file {}: {}", path.display(), e);
+            return;
+        }
+    };
+    while let Some(mut line) = lines.recv().await {
+        line.push('\n');
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            error!("Failed to write journal file {}: {}", path.display(), e);
+        }
+    }
+}
+
+// Credentials are masked, since the journal is served and written to disk as is
+fn header_map(headers: &HeaderMap, api_key_header: &str) -> BTreeMap<String, String> {
+    let mut map: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in headers {
+        let secret = name == header::AUTHORIZATION
+            || name == header::COOKIE
+            || name.as_str() == api_key_header;
+        let value = if secret {
+            REDACTED.into()
+        } else {
+            String::from_utf8_lossy(value.as_bytes())
+        };
+        map.entry(name.to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+    map
+}
+
+fn body_value(bytes: &[u8]) -> Value {
+    if bytes.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+// Read at most `MAX_JOURNAL_BODY_BYTES + 1` bytes of `body`. Returns its journal
+// value and a body that still yields every byte, so large uploads keep streaming
+// to the handler instead of being held in memory.
+async fn peek_body(body: Body, headers: &HeaderMap) -> Result<(Value, Body), axum::Error> {
+    let mut stream = body.into_data_stream();
+    let mut head = Vec::new();
+    while head.len() <= MAX_JOURNAL_BODY_BYTES {
+        match stream.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => {
+                let value = body_value(&head);
+                return Ok((value, Body::from(head)));
+            }
+        }
+    }
+
+    let size = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .map(|length| format!("<{} bytes>", length))
+        .unwrap_or_else(|| format!("<over {} bytes>", MAX_JOURNAL_BODY_BYTES));
+    let head = stream::once(async { Ok(Bytes::from(head)) });
+    Ok((Value::String(size), Body::from_stream(head.chain(stream))))
+}
+
+/// Journal every request with the status it was answered with.
+pub async fn record_requests(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Requests to the control API and metrics scrapes inspect the mock rather than use it
+    let path = request.uri().path();
+    if path.starts_with(ADMIN_PREFIX) || path == METRICS_PATH {
+        return next.run(request).await;
+    }
+
+    let start = std::time::Instant::now();
+    let (parts, body) = request.into_parts();
+    let Ok((body_value, body)) = peek_body(body, &parts.headers).await else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let mut entry = JournalEntry {
+        id: 0,
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(str::to_string),
+        headers: header_map(&parts.headers, &state.auth.api_key_header()),
+        session: session_id(&parts.headers).map(str::to_string),
+        body: body_value,
+        status: 0,
+        elapsed_ms: 0,
+    };
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+    entry.status = response.status().as_u16();
+    entry.elapsed_ms = start.elapsed().as_millis();
+    state.journal.record(entry);
+    response
+}
+
+// `404`, or a class such as `4xx`
+fn status_matches(filter: &str, status: u16) -> bool {
+    let filter = filter.to_ascii_lowercase();
+    match filter.strip_suffix("xx") {
+        Some(class) => class == (status / 100).to_string(),
+        None => filter == status.to_string(),
+    }
+}
+
+// An exact path, or a prefix ending in `*`
+fn path_matches(filter: &str, path: &str) -> bool {
+    let filter = format!("/{}", filter.trim_start_matches('/'));
+    match filter.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == filter,
+    }
+}
+
+/// `GET /__chimera/requests`, filtered by `method`, `path`, `status`, `session`,
+/// `since` (an entry id) and `limit` (the newest N).
+pub async fn list_requests(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let since = match params.get("since").map(|since| since.parse::<u64>()) {
+        Some(Ok(since)) => Some(since),
+        Some(Err(_)) => {
+            return error_response(StatusCode::BAD_REQUEST, "`since` must be an entry id")
+        }
+        None => None,
+    };
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        Some(Ok(limit)) => Some(limit),
+        Some(Err(_)) => return error_response(StatusCode::BAD_REQUEST, "`limit` must be a number"),
+        None => None,
+    };
+
+    let mut entries: Vec<JournalEntry> = state
+        .journal
+        .entries()
+        .into_iter()
+        .filter(|entry| {
+            params
+                .get("method")
+                .is_none_or(|method| entry.method.eq_ignore_ascii_case(method))
+                && params
+                    .get("path")
+                    .is_none_or(|path| path_matches(path, &entry.path))
+                && params
+                    .get("status")
+                    .is_none_or(|status| status_matches(status, entry.status))
+                && params
+                    .get("session")
+                    .is_none_or(|session| entry.session.as_ref() == Some(session))
+                && since.is_none_or(|since| entry.id > since)
+        })
+        .collect();
+    if let Some(limit) = limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+
+    (
+        [(
+            header::HeaderName::from_static("x-total-count"),
+            entries.len(),
+        )],
+        Json(entries),
+    )
+        .into_response()
+}
+
+/// `DELETE /__chimera/requests`
+pub async fn clear_requests(State(state): State<Arc<AppState>>) -> StatusCode {
+    state.journal.clear();
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::json;
+
+    fn entry(path: &str) -> JournalEntry {
+        JournalEntry {
+            id: 0,
+            timestamp: String::new(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            headers: BTreeMap::new(),
+            session: None,
+            body: Value::Null,
+            status: 200,
+            elapsed_ms: 0,
+        }
+    }
+
+    #[test]
+    fn masks_credential_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer abc".parse().unwrap());
+        headers.insert(header::COOKIE, "sid=1".parse().unwrap());
+        headers.insert("x-token", "k3y".parse().unwrap());
+        headers.insert(header::ACCEPT, "*/*".parse().unwrap());
+
+        let map = header_map(&headers, "x-token");
+        assert_eq!(map["authorization"], REDACTED);
+        assert_eq!(map["cookie"], REDACTED);
+        assert_eq!(map["x-token"], REDACTED);
+        assert_eq!(map["accept"], "*/*");
+    }
+
+    #[test]
+    fn matches_statuses_and_classes() {
+        assert!(status_matches("404", 404));
+        assert!(status_matches("4xx", 404));
+        assert!(status_matches("4XX", 429));
+        assert!(!status_matches("5xx", 404));
+        assert!(!status_matches("200", 201));
+    }
+
+    #[test]
+    fn matches_paths_and_prefixes() {
+        assert!(path_matches("/users", "/users"));
+        assert!(path_matches("users", "/users"));
+        assert!(!path_matches("/users", "/users/1"));
+        assert!(path_matches("/users*", "/users/1"));
+    }
+
+    #[test]
+    fn keeps_the_newest_entries() {
+        let journal = Journal::spawn(&JournalConfig {
+            size: Some(2),
+            file: None,
+        });
+        for path in ["/a", "/b", "/c"] {
+            journal.record(entry(path));
+        }
+        let entries = journal.entries();
+        let kept: Vec<(u64, &str)> = entries
+            .iter()
+            .map(|entry| (entry.id, entry.path.as_str()))
+            .collect();
+        assert_eq!(kept, [(2, "/b"), (3, "/c")]);
+
+        journal.clear();
+        assert!(journal.entries().is_empty());
+    }
+
+    #[test]
+    fn parses_json_bodies_and_keeps_text() {
+        assert_eq!(body_value(b""), Value::Null);
+        assert_eq!(body_value(br#"{"a":1}"#), json!({"a": 1}));
+        assert_eq!(body_value(b"a=1&b=2"), json!("a=1&b=2"));
+    }
+
+    #[tokio::test]
+    async fn passes_large_bodies_through_whole() {
+        let big = vec![b'x'; MAX_JOURNAL_BODY_BYTES * 3];
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, big.len().into());
+
+        let (value, body) = peek_body(Body::from(big.clone()), &headers).await.unwrap();
+        assert_eq!(value, json!(format!("<{} bytes>", big.len())));
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), big);
+    }
+
+    #[tokio::test]
+    async fn small_bodies_are_journaled() {
+        let (value, body) = peek_body(Body::from(r#"{"a":1}"#), &HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(value, json!({"a": 1}));
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), r#"{"a":1}"#);
+    }
+}
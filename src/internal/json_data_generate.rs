@@ -20,19 +20,23 @@ pub struct JsonDataGeneratorSchema {
     pub routes: Vec<RouteStruct>,
 }
 
-pub fn generate_json_from_schema(schema: JsonDataGeneratorSchema) -> Value {
+pub fn generate_json_from_schema(schema: JsonDataGeneratorSchema) -> Result<Value, String> {
     let mut rng = StdRng::from_rng(&mut rand::rng());
     let mut result = Map::new();
 
     if schema.routes.is_empty() {
-        eprintln!("Schema file must contain at least one route when `auto-generate-data` is enabled");
-        std::process::exit(1);
+        return Err(
+            "Schema file must contain at least one route when `auto-generate-data` is enabled"
+                .to_string(),
+        );
     }
 
     for route in schema.routes.iter() {
         if route.null_percentage > 90 {
-            eprintln!("`null_percentage` for route `{}` must be between 0 and 90", route.path);
-            std::process::exit(1);
+            return Err(format!(
+                "`null_percentage` for route `{}` must be between 0 and 90",
+                route.path
+            ));
         }
 
         let mut route_data = Vec::with_capacity(route.no_of_entries as usize);
@@ -54,10 +58,10 @@ pub fn generate_json_from_schema(schema: JsonDataGeneratorSchema) -> Value {
         result.insert(route.path.clone(), Value::Array(route_data));
     }
 
-    Value::Object(result)
+    Ok(Value::Object(result))
 }
 
-fn generate_value(field_type: &str, rng: &mut StdRng, index: u64) -> Value {
+pub fn generate_value(field_type: &str, rng: &mut StdRng, index: u64) -> Value {
     match field_type {
         "name" => Value::String(Name().fake_with_rng(rng)),
         "lorem" => Value::String(Paragraph(1..3).fake_with_rng(rng)),
@@ -0,0 +1,186 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum JwtError {
+    #[error("malformed token")]
+    Malformed,
+    #[error("unsupported algorithm, expected HS256")]
+    Algorithm,
+    #[error("invalid signature")]
+    Signature,
+    #[error("token has expired")]
+    Expired,
+    #[error("token is not valid yet")]
+    NotYetValid,
+    #[error("unexpected issuer")]
+    Issuer,
+    #[error("unexpected audience")]
+    Audience,
+}
+
+fn signature(secret: &str, signing_input: &str) -> HmacSha256 {
+    // HMAC accepts keys of any length
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC key");
+    mac.update(signing_input.as_bytes());
+    mac
+}
+
+/// Sign `claims` as an HS256 JWT.
+pub fn encode(claims: &Map<String, Value>, secret: &str) -> String {
+    let header = json!({ "alg": "HS256", "typ": "JWT" });
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(Value::Object(claims.clone()).to_string())
+    );
+    let signature = signature(secret, &signing_input).finalize().into_bytes();
+    format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// Claims a token must carry besides a valid signature.
+#[derive(Debug, Default)]
+pub struct Validation<'a> {
+    pub issuer: Option<&'a str>,
+    pub audience: Option<&'a str>,
+}
+
+/// Check an HS256 token's signature and its `exp`, `nbf`, `iss` and `aud` claims,
+/// returning the claims.
+pub fn decode(
+    token: &str,
+    secret: &str,
+    validation: &Validation,
+) -> Result<Map<String, Value>, JwtError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(claims), Some(sig), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(JwtError::Malformed);
+    };
+    let decode_part = |part: &str| -> Result<Value, JwtError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| JwtError::Malformed)?;
+        serde_json::from_slice(&bytes).map_err(|_| JwtError::Malformed)
+    };
+
+    if decode_part(header)?.get("alg").and_then(Value::as_str) != Some("HS256") {
+        return Err(JwtError::Algorithm);
+    }
+    let sig = URL_SAFE_NO_PAD
+        .decode(sig)
+        .map_err(|_| JwtError::Malformed)?;
+    signature(secret, &format!("{}.{}", header, claims))
+        .verify_slice(&sig)
+        .map_err(|_| JwtError::Signature)?;
+
+    let Value::Object(claims) = decode_part(claims)? else {
+        return Err(JwtError::Malformed);
+    };
+    let now = chrono::Utc::now().timestamp();
+    let time_claim = |name: &str| claims.get(name).and_then(Value::as_i64);
+    if time_claim("exp").is_some_and(|exp| now >= exp) {
+        return Err(JwtError::Expired);
+    }
+    if time_claim("nbf").is_some_and(|nbf| now < nbf) {
+        return Err(JwtError::NotYetValid);
+    }
+    if let Some(issuer) = validation.issuer {
+        if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+            return Err(JwtError::Issuer);
+        }
+    }
+    if let Some(audience) = validation.audience {
+        // `aud` may be a single string or a list
+        let matches = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !matches {
+            return Err(JwtError::Audience);
+        }
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn round_trips_claims() {
+        let claims = claims(json!({"sub": "1", "role": "admin", "exp": in_an_hour()}));
+        let token = encode(&claims, "secret");
+        assert_eq!(decode(&token, "secret", &Validation::default()), Ok(claims));
+    }
+
+    #[test]
+    fn rejects_a_token_signed_with_another_secret() {
+        let token = encode(&claims(json!({"sub": "1"})), "secret");
+        assert_eq!(
+            decode(&token, "other", &Validation::default()),
+            Err(JwtError::Signature)
+        );
+    }
+
+    #[test]
+    fn rejects_expired_and_future_tokens() {
+        let now = chrono::Utc::now().timestamp();
+        let expired = encode(&claims(json!({"exp": now - 1})), "secret");
+        assert_eq!(
+            decode(&expired, "secret", &Validation::default()),
+            Err(JwtError::Expired)
+        );
+        let early = encode(&claims(json!({"nbf": now + 60})), "secret");
+        assert_eq!(
+            decode(&early, "secret", &Validation::default()),
+            Err(JwtError::NotYetValid)
+        );
+    }
+
+    #[test]
+    fn checks_issuer_and_audience() {
+        let token = encode(
+            &claims(json!({"iss": "chimera", "aud": ["web", "cli"]})),
+            "s",
+        );
+        let validation = |issuer, audience| Validation { issuer, audience };
+        assert!(decode(&token, "s", &validation(Some("chimera"), Some("cli"))).is_ok());
+        assert_eq!(
+            decode(&token, "s", &validation(Some("other"), None)),
+            Err(JwtError::Issuer)
+        );
+        assert_eq!(
+            decode(&token, "s", &validation(None, Some("mobile"))),
+            Err(JwtError::Audience)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        for token in ["", "a.b", "a.b.c.d", "not.base64!.sig"] {
+            assert_eq!(
+                decode(token, "s", &Validation::default()),
+                Err(JwtError::Malformed),
+                "{}",
+                token
+            );
+        }
+    }
+}
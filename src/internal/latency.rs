@@ -0,0 +1,379 @@
+use crate::internal::chimera::AppState;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use futures::{stream, Stream, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+// Throttled bodies are sent in slices of this many per second, so a slow link
+// still delivers bytes steadily instead of in one late burst
+const THROTTLE_TICKS_PER_SEC: u64 = 10;
+
+tokio::task_local! {
+    // Latency injected while serving the current request, read by the metrics
+    pub static INJECTED_LATENCY: Cell<Duration>;
+}
+
+/// How long a simulated request takes: a fixed delay, a uniform range, a normal
+/// distribution or a percentile profile. All values are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Fixed(u64),
+    Uniform { min: u64, max: u64 },
+    Normal { mean: f64, stddev: f64 },
+    Profile { p50: u64, p95: u64, p99: u64 },
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency::Fixed(0)
+    }
+}
+
+fn parse_ms(value: &str) -> Option<u64> {
+    value.trim().trim_end_matches("ms").trim().parse().ok()
+}
+
+impl Latency {
+    /// Parse `100`, `100ms`, `50..200ms`, `normal(100,20)` or
+    /// `p50=40,p95=200,p99=800`. Missing p95 / p99 default to the percentile below.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let invalid = || {
+            format!(
+                "Invalid latency `{}`. Use 100, 50..200ms, normal(100,20) or p50=40,p95=200,p99=800",
+                spec
+            )
+        };
+
+        if let Some(args) = spec
+            .strip_prefix("normal(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let (mean, stddev) = args.split_once(',').ok_or_else(invalid)?;
+            let (mean, stddev) = (
+                parse_ms(mean).ok_or_else(invalid)?,
+                parse_ms(stddev).ok_or_else(invalid)?,
+            );
+            return Ok(Latency::Normal {
+                mean: mean as f64,
+                stddev: stddev as f64,
+            });
+        }
+
+        if spec.starts_with('p') {
+            let mut percentiles = [None; 3];
+            for part in spec.split(',') {
+                let (name, value) = part.split_once('=').ok_or_else(invalid)?;
+                let slot = match name.trim() {
+                    "p50" => 0,
+                    "p95" => 1,
+                    "p99" => 2,
+                    _ => return Err(invalid()),
+                };
+                percentiles[slot] = Some(parse_ms(value).ok_or_else(invalid)?);
+            }
+            let p50 = percentiles[0].ok_or_else(invalid)?;
+            let p95 = percentiles[1].unwrap_or(p50);
+            let p99 = percentiles[2].unwrap_or(p95);
+            return Latency::profile(p50, p95, p99);
+        }
+
+        if let Some((min, max)) = spec.split_once("..") {
+            let (min, max) = (
+                parse_ms(min).ok_or_else(invalid)?,
+                parse_ms(max).ok_or_else(invalid)?,
+            );
+            if min > max {
+                return Err(format!(
+                    "Invalid latency `{}`: {} is above {}",
+                    spec, min, max
+                ));
+            }
+            return Ok(Latency::Uniform { min, max });
+        }
+
+        parse_ms(spec).map(Latency::Fixed).ok_or_else(invalid)
+    }
+
+    fn profile(p50: u64, p95: u64, p99: u64) -> Result<Self, String> {
+        if p50 > p95 || p95 > p99 {
+            return Err(format!(
+                "Invalid latency profile: p50 ({}) <= p95 ({}) <= p99 ({}) must hold",
+                p50, p95, p99
+            ));
+        }
+        Ok(Latency::Profile { p50, p95, p99 })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Latency::Fixed(0)
+    }
+
+    /// Draw one delay from the distribution.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let ms = match *self {
+            Latency::Fixed(ms) => ms as f64,
+            Latency::Uniform { min, max } => rng.random_range(min..=max) as f64,
+            Latency::Normal { mean, stddev } => {
+                // Box-Muller transform; negative draws are clamped to no delay
+                let u1: f64 = 1.0 - rng.random::<f64>();
+                let u2: f64 = rng.random();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                mean + stddev * z
+            }
+            Latency::Profile { p50, p95, p99 } => {
+                // Interpolate the quantile curve through the given percentiles,
+                // with a tail past p99 as long as the p95..p99 stretch
+                let (p50, p95, p99) = (p50 as f64, p95 as f64, p99 as f64);
+                let points = [
+                    (0.0, 0.0),
+                    (0.5, p50),
+                    (0.95, p95),
+                    (0.99, p99),
+                    (1.0, p99 + (p99 - p95)),
+                ];
+                let q: f64 = rng.random();
+                points
+                    .windows(2)
+                    .find(|pair| q <= pair[1].0)
+                    .map(|pair| {
+                        let ((q0, v0), (q1, v1)) = (pair[0], pair[1]);
+                        v0 + (v1 - v0) * (q - q0) / (q1 - q0)
+                    })
+                    .unwrap_or(p99)
+            }
+        };
+        Duration::from_millis(ms.max(0.0).round() as u64)
+    }
+
+    /// Sleep for one sampled delay.
+    pub async fn wait(&self) {
+        if self.is_zero() {
+            return;
+        }
+        let delay = self.sample(&mut rand::rng());
+        if !delay.is_zero() {
+            let _ = INJECTED_LATENCY.try_with(|injected| injected.set(injected.get() + delay));
+            sleep(delay).await;
+        }
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Latency::Fixed(ms) => write!(f, "{}ms", ms),
+            Latency::Uniform { min, max } => write!(f, "{}..{}ms", min, max),
+            Latency::Normal { mean, stddev } => write!(f, "normal({},{})", mean, stddev),
+            Latency::Profile { p50, p95, p99 } => {
+                write!(f, "p50={},p95={},p99={}", p50, p95, p99)
+            }
+        }
+    }
+}
+
+impl Serialize for Latency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Config files may give a number of ms, a spec string or a percentile table
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LatencyValue {
+    Millis(u64),
+    Spec(String),
+    Profile {
+        p50: u64,
+        p95: Option<u64>,
+        p99: Option<u64>,
+    },
+}
+
+impl<'de> Deserialize<'de> for Latency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let latency = match LatencyValue::deserialize(deserializer)? {
+            LatencyValue::Millis(ms) => Ok(Latency::Fixed(ms)),
+            LatencyValue::Spec(spec) => Latency::parse(&spec),
+            LatencyValue::Profile { p50, p95, p99 } => {
+                let p95 = p95.unwrap_or(p50);
+                Latency::profile(p50, p95, p99.unwrap_or(p95))
+            }
+        };
+        latency.map_err(serde::de::Error::custom)
+    }
+}
+
+/// Stream `body` in `chunk_size` pieces, pausing before each one. Body errors
+/// are passed through.
+pub fn drip(body: Body, chunk_size: usize, pause: Duration) -> Body {
+    Body::from_stream(slices(body, chunk_size).then(move |slice| async move {
+        if slice.is_ok() {
+            sleep(pause).await;
+        }
+        slice
+    }))
+}
+
+/// Limit response bodies to the route's (or the global) `bandwidth` in bytes/sec.
+/// The body is passed through as it streams, so errors such as a chaos `drop`
+/// still reach the client.
+pub async fn throttle_bandwidth(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request.uri().path().trim_start_matches('/').to_string();
+    let method = request.method().clone();
+    let response = next.run(request).await;
+
+    let bandwidth = state.bandwidth_for(&route, method.as_str());
+    if bandwidth == 0 {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // The length is still known, so clients can show progress
+    if let Some(length) = body.size_hint().exact() {
+        parts.headers.insert(header::CONTENT_LENGTH, length.into());
+    }
+    Response::from_parts(parts, throttle(body, bandwidth))
+}
+
+// Re-slice the body into pieces of about a tick's worth of bytes, each sent
+// after the time it takes at `bandwidth`
+fn throttle(body: Body, bandwidth: u64) -> Body {
+    let slice_size = (bandwidth / THROTTLE_TICKS_PER_SEC).max(1) as usize;
+    Body::from_stream(slices(body, slice_size).then(move |slice| async move {
+        if let Ok(bytes) = &slice {
+            let pause = bytes.len() as f64 / bandwidth as f64;
+            sleep(Duration::from_secs_f64(pause)).await;
+        }
+        slice
+    }))
+}
+
+// The body's chunks cut into pieces of at most `size` bytes, as they arrive
+fn slices(body: Body, size: usize) -> impl Stream<Item = Result<Bytes, axum::Error>> {
+    let size = size.max(1);
+    body.into_data_stream().flat_map(move |chunk| match chunk {
+        Ok(bytes) => stream::iter(
+            (0..bytes.len())
+                .step_by(size)
+                .map(move |start| Ok(bytes.slice(start..(start + size).min(bytes.len())))),
+        )
+        .left_stream(),
+        Err(e) => stream::once(async { Err(e) }).right_stream(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn parses_every_spec() {
+        assert_eq!(Latency::parse("100"), Ok(Latency::Fixed(100)));
+        assert_eq!(Latency::parse(" 100ms "), Ok(Latency::Fixed(100)));
+        assert_eq!(
+            Latency::parse("50..200ms"),
+            Ok(Latency::Uniform { min: 50, max: 200 })
+        );
+        assert_eq!(
+            Latency::parse("normal(100,20)"),
+            Ok(Latency::Normal {
+                mean: 100.0,
+                stddev: 20.0
+            })
+        );
+        assert_eq!(
+            Latency::parse("p50=40,p99=800"),
+            Ok(Latency::Profile {
+                p50: 40,
+                p95: 40,
+                p99: 800
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for spec in ["fast", "200..50", "normal(100)", "p95=10", "p50=90,p95=10"] {
+            assert!(Latency::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn displays_as_a_parseable_spec() {
+        for spec in [
+            "100ms",
+            "50..200ms",
+            "normal(100,20)",
+            "p50=40,p95=200,p99=800",
+        ] {
+            let latency = Latency::parse(spec).unwrap();
+            assert_eq!(Latency::parse(&latency.to_string()), Ok(latency));
+        }
+    }
+
+    #[test]
+    fn samples_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let uniform = Latency::Uniform { min: 50, max: 60 };
+        let profile = Latency::Profile {
+            p50: 10,
+            p95: 20,
+            p99: 30,
+        };
+        for _ in 0..1000 {
+            let ms = uniform.sample(&mut rng).as_millis();
+            assert!((50..=60).contains(&ms));
+            assert!(profile.sample(&mut rng).as_millis() <= 40);
+        }
+        assert_eq!(
+            Latency::Fixed(25).sample(&mut rng),
+            Duration::from_millis(25)
+        );
+    }
+
+    #[tokio::test]
+    async fn throttled_body_arrives_whole() {
+        let body = throttle(Body::from("0123456789abcdef"), 10_000);
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"0123456789abcdef");
+    }
+
+    #[tokio::test]
+    async fn throttled_body_keeps_stream_errors() {
+        let failing = Body::from_stream(stream::iter([
+            Ok(Bytes::from_static(b"partial")),
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionAborted,
+                "dropped",
+            )),
+        ]));
+        assert!(to_bytes(throttle(failing, 10_000), usize::MAX)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn drip_streams_every_byte() {
+        let body = drip(Body::from("0123456789"), 3, Duration::from_millis(1));
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), "0123456789");
+    }
+}
@@ -0,0 +1,136 @@
+use crate::internal::json_data_generate::{generate_json_from_schema, JsonDataGeneratorSchema};
+use csv::Reader;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+pub const CORS_FILE: &str = "chimera.cors";
+
+pub fn file_extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+// Build the route store from a data file, a schema file (`-X`) or a CSV schema
+pub fn parse_data(content: &str, extension: &str, auto_generate: bool) -> Result<Value, String> {
+    match extension {
+        "csv" => {
+            let mut reader = Reader::from_reader(content.as_bytes());
+            let mut schema_entries = Vec::new();
+
+            for result in reader.records() {
+                let record = result.map_err(|e| format!("Failed to read CSV record: {}", e))?;
+
+                if record.len() >= 4 {
+                    let path = record.get(0).unwrap_or("").to_string();
+                    let no_of_entries: u32 = record
+                        .get(1)
+                        .unwrap_or("0")
+                        .parse()
+                        .map_err(|_| "Invalid no_of_entries value".to_string())?;
+                    let null_percentage: u8 = record
+                        .get(2)
+                        .unwrap_or("0")
+                        .parse()
+                        .map_err(|_| "Invalid null_percentage value".to_string())?;
+                    let schema_json: Value = serde_json::from_str(record.get(3).unwrap_or("{}"))
+                        .map_err(|e| format!("Invalid schema JSON in CSV: {}", e))?;
+
+                    // Create each route entry
+                    let mut route_entry = Map::new();
+                    route_entry.insert("path".to_string(), Value::String(path));
+                    route_entry.insert(
+                        "no_of_entries".to_string(),
+                        Value::Number(no_of_entries.into()),
+                    );
+                    route_entry.insert("schema".to_string(), schema_json);
+                    route_entry.insert(
+                        "null_percentage".to_string(),
+                        Value::Number(null_percentage.into()),
+                    );
+
+                    schema_entries.push(Value::Object(route_entry));
+                }
+            }
+
+            let mut routes_object = Map::new();
+            routes_object.insert("routes".to_string(), Value::Array(schema_entries));
+            let routes_value = Value::Object(routes_object);
+
+            let schema: JsonDataGeneratorSchema = serde_json::from_value(routes_value)
+                .map_err(|e| format!("Failed to convert CSV data to schema format: {}", e))?;
+            generate_json_from_schema(schema)
+        }
+        "json" => {
+            if auto_generate {
+                let schema: JsonDataGeneratorSchema =
+                    serde_json::from_str(content).map_err(|e| {
+                        format!("Invalid schema format for auto data generation: {}", e)
+                    })?;
+                generate_json_from_schema(schema)
+            } else {
+                let content: Value = serde_json::from_str(content)
+                    .map_err(|e| format!("Invalid Json format: {}", e))?;
+
+                if content.get("routes").is_some() {
+                    return Err("Please pass a data file .json for your routes as \
+                        `auto-generate-data` is disabled"
+                        .to_string());
+                }
+
+                if !content.is_object() {
+                    return Err(
+                        "The given json file is a JSON Array! It should be a JSON Object"
+                            .to_string(),
+                    );
+                }
+                Ok(content)
+            }
+        }
+        _ => Err("Unsupported file format. Please provide a .json or .csv file".to_string()),
+    }
+}
+
+// One origin per line; a missing file means no restriction
+pub async fn load_cors_origins() -> Option<Vec<String>> {
+    if !Path::new(CORS_FILE).exists() {
+        return None;
+    }
+    Some(
+        tokio::fs::read_to_string(CORS_FILE)
+            .await
+            .unwrap_or_default()
+            .lines()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn extension_is_lower_cased() {
+        assert_eq!(file_extension("data/Users.JSON"), "json");
+        assert_eq!(file_extension("data"), "");
+    }
+
+    #[test]
+    fn parses_data_files() {
+        let value = parse_data(r#"{"users": []}"#, "json", false).unwrap();
+        assert_eq!(value, json!({"users": []}));
+    }
+
+    #[test]
+    fn rejects_what_startup_rejects() {
+        assert!(parse_data("[]", "json", false).is_err());
+        assert!(parse_data(r#"{"routes": []}"#, "json", false).is_err());
+        assert!(parse_data("{", "json", false).is_err());
+        assert!(parse_data("users: []", "yaml", false).is_err());
+    }
+}
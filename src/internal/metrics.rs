@@ -0,0 +1,398 @@
+use crate::internal::chimera::AppState;
+use crate::internal::latency::INJECTED_LATENCY;
+use crate::internal::records::split_record_path;
+use crate::internal::relations::NestedRoute;
+use crate::internal::routes::RouteTable;
+use crate::internal::ws_handlers::WsSharedState;
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Marks a response as the `server_busy_response` given when the data lock
+/// couldn't be taken in time.
+#[derive(Clone, Copy)]
+pub struct LockTimeout;
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+// (route, method)
+type Labels = (String, String);
+
+#[derive(Default)]
+struct Recorded {
+    requests: BTreeMap<(String, String, u16), u64>,
+    handler: BTreeMap<Labels, Histogram>,
+    injected: BTreeMap<Labels, Histogram>,
+    lock_timeouts: BTreeMap<Labels, u64>,
+}
+
+/// Request counters and latency histograms for `GET /metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl Metrics {
+    fn record(
+        &self,
+        labels: Labels,
+        status: u16,
+        handler: Duration,
+        injected: Duration,
+        lock_timeout: bool,
+    ) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        *recorded
+            .requests
+            .entry((labels.0.clone(), labels.1.clone(), status))
+            .or_default() += 1;
+        recorded
+            .handler
+            .entry(labels.clone())
+            .or_default()
+            .observe(handler);
+        if !injected.is_zero() {
+            recorded
+                .injected
+                .entry(labels.clone())
+                .or_default()
+                .observe(injected);
+        }
+        if lock_timeout {
+            *recorded.lock_timeouts.entry(labels).or_default() += 1;
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        let recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+
+        header(
+            out,
+            "chimera_http_requests_total",
+            "counter",
+            "HTTP requests by route, method and status.",
+        );
+        for ((route, method, status), count) in &recorded.requests {
+            let _ = writeln!(
+                out,
+                "chimera_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(route),
+                method,
+                status,
+                count
+            );
+        }
+
+        header(
+            out,
+            "chimera_http_request_duration_seconds",
+            "histogram",
+            "Time spent handling requests, not counting injected latency.",
+        );
+        render_histograms(
+            out,
+            "chimera_http_request_duration_seconds",
+            &recorded.handler,
+        );
+
+        header(
+            out,
+            "chimera_injected_latency_seconds",
+            "histogram",
+            "Latency added by --latency and route settings.",
+        );
+        render_histograms(out, "chimera_injected_latency_seconds", &recorded.injected);
+
+        header(
+            out,
+            "chimera_lock_timeouts_total",
+            "counter",
+            "Requests answered as busy because the data lock wasn't free in time.",
+        );
+        for ((route, method), count) in &recorded.lock_timeouts {
+            let _ = writeln!(
+                out,
+                "chimera_lock_timeouts_total{{route=\"{}\",method=\"{}\"}} {}",
+                escape(route),
+                method,
+                count
+            );
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn render_histograms(out: &mut String, name: &str, histograms: &BTreeMap<Labels, Histogram>) {
+    for ((route, method), histogram) in histograms {
+        let labels = format!("route=\"{}\",method=\"{}\"", escape(route), method);
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, histogram.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// The data key or route definition a path matched, without a trailing record id,
+// so `/api/v2/users/1` and `/api/v2/users/2` share the `api/v2/users` series.
+// Anything unmatched counts under its first segment, so stray paths can't each
+// add a series.
+fn route_label(data: &Value, routes: &RouteTable, path: &str) -> String {
+    let route = path.trim_matches('/');
+    if route.is_empty() {
+        return "/".to_string();
+    }
+    if data.get(route).is_some() {
+        return route.to_string();
+    }
+    if let Some((key, _, _)) = routes.find_entry(route) {
+        return key.to_string();
+    }
+    if let Some((collection, _)) = split_record_path(data, route) {
+        if data.get(collection).is_some() {
+            return collection.to_string();
+        }
+    }
+    if let Some(nested) = NestedRoute::parse(data, route) {
+        return format!("{}/:id/{}", nested.parent, nested.child);
+    }
+    route.split('/').next().unwrap_or(route).to_string()
+}
+
+/// Records in a top-level value: the length of a collection, 1 for a single object.
+pub fn record_count(value: &Value) -> usize {
+    match value {
+        Value::Array(records) => records.len(),
+        _ => 1,
+    }
+}
+
+fn render_records(out: &mut String, data: &Value) {
+    header(out, "chimera_records", "gauge", "Records per collection.");
+    for (collection, value) in data.as_object().into_iter().flatten() {
+        let _ = writeln!(
+            out,
+            "chimera_records{{collection=\"{}\"}} {}",
+            escape(collection),
+            record_count(value)
+        );
+    }
+}
+
+fn metrics_response(body: String) -> Response {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response()
+}
+
+/// Count each request and time it, telling the handler's own time apart from the
+/// latency it was told to inject.
+pub async fn track_metrics(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let route = route_label(
+        &*state.json_value.read().await,
+        &state.routes(),
+        request.uri().path(),
+    );
+    let labels = (route, request.method().to_string());
+    let (response, injected) = INJECTED_LATENCY
+        .scope(Cell::new(Duration::ZERO), async {
+            let response = next.run(request).await;
+            (response, INJECTED_LATENCY.with(Cell::get))
+        })
+        .await;
+
+    state.metrics.record(
+        labels,
+        response.status().as_u16(),
+        start.elapsed().saturating_sub(injected),
+        injected,
+        response.extensions().get::<LockTimeout>().is_some(),
+    );
+    response
+}
+
+/// `GET /metrics` in HTTP mode.
+pub async fn http_metrics(State(state): State<Arc<AppState>>) -> Response {
+    let mut out = String::new();
+    state.metrics.render(&mut out);
+    render_records(&mut out, &*state.json_value.read().await);
+    metrics_response(out)
+}
+
+/// `GET /metrics` in WebSocket mode.
+pub async fn ws_metrics(State((_, json_data, connections)): State<WsSharedState>) -> Response {
+    let mut out = String::new();
+
+    let mut per_route: BTreeMap<String, usize> = BTreeMap::new();
+    for connection in connections.read().await.values() {
+        *per_route.entry(connection.route.clone()).or_default() += 1;
+    }
+    header(
+        &mut out,
+        "chimera_websocket_connections",
+        "gauge",
+        "Open WebSocket connections by route.",
+    );
+    for (route, count) in per_route {
+        let _ = writeln!(
+            out,
+            "chimera_websocket_connections{{route=\"{}\"}} {}",
+            escape(&route),
+            count
+        );
+    }
+
+    render_records(&mut out, &*json_data.read().await);
+    metrics_response(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn labels(route: &str, method: &str) -> Labels {
+        (route.to_string(), method.to_string())
+    }
+
+    #[test]
+    fn records_share_their_collection_series() {
+        let data = json!({
+            "users": [{"id": 1}],
+            "posts": [{"id": 1}],
+            "api/v2/users": [{"id": 1}],
+            "profile": {"name": "a"},
+        });
+        let routes: RouteTable =
+            serde_json::from_value(json!({"orders/:id/items": {}, "health": {}})).unwrap();
+        let label = |path: &str| route_label(&data, &routes, path);
+
+        assert_eq!(label("/users/1"), "users");
+        assert_eq!(label("/users/"), "users");
+        assert_eq!(label("/api/v2/users"), "api/v2/users");
+        assert_eq!(label("/api/v2/users/7"), "api/v2/users");
+        assert_eq!(label("/profile"), "profile");
+        assert_eq!(label("/posts/1/users"), "posts/:id/users");
+        assert_eq!(label("/orders/5/items"), "orders/:id/items");
+        assert_eq!(label("/health"), "health");
+        assert_eq!(label("/unknown/a/b"), "unknown");
+        assert_eq!(label("/"), "/");
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(300));
+        histogram.observe(Duration::from_secs(60));
+
+        assert_eq!(histogram.buckets[0], 0);
+        assert_eq!(histogram.buckets[1], 1);
+        assert_eq!(histogram.buckets[7], 2);
+        assert_eq!(histogram.buckets[BUCKETS.len() - 1], 2);
+        assert_eq!(histogram.count, 3);
+    }
+
+    #[test]
+    fn renders_counters_and_histograms() {
+        let metrics = Metrics::default();
+        let users = labels("users", "GET");
+        metrics.record(
+            users.clone(),
+            200,
+            Duration::from_millis(2),
+            Duration::ZERO,
+            false,
+        );
+        metrics.record(
+            users.clone(),
+            200,
+            Duration::from_millis(2),
+            Duration::from_millis(50),
+            false,
+        );
+        metrics.record(users, 500, Duration::from_millis(100), Duration::ZERO, true);
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        for line in [
+            "# TYPE chimera_http_requests_total counter",
+            "chimera_http_requests_total{route=\"users\",method=\"GET\",status=\"200\"} 2",
+            "chimera_http_requests_total{route=\"users\",method=\"GET\",status=\"500\"} 1",
+            "chimera_http_request_duration_seconds_bucket{route=\"users\",method=\"GET\",le=\"+Inf\"} 3",
+            "chimera_injected_latency_seconds_count{route=\"users\",method=\"GET\"} 1",
+            "chimera_lock_timeouts_total{route=\"users\",method=\"GET\"} 1",
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing `{}` in\n{}", line, out);
+        }
+    }
+
+    #[test]
+    fn counts_records_per_collection() {
+        let mut out = String::new();
+        render_records(
+            &mut out,
+            &json!({"users": [{}, {}], "profile": {"name": "a"}}),
+        );
+        assert!(out.contains("chimera_records{collection=\"users\"} 2\n"));
+        assert!(out.contains("chimera_records{collection=\"profile\"} 1\n"));
+    }
+}
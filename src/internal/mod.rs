@@ -1,6 +1,27 @@
+pub mod admin;
+pub mod auth;
+pub mod chaos;
 pub mod chimera;
+pub mod config_file;
+pub mod health;
 pub mod helpers;
 pub mod http_handlers;
+pub mod journal;
 pub mod json_data_generate;
+pub mod jwt;
+pub mod latency;
+pub mod loader;
+pub mod metrics;
+pub mod persist;
 pub mod port;
+pub mod proxy;
+pub mod query;
+pub mod rate_limit;
+pub mod records;
+pub mod relations;
+pub mod reload;
+pub mod routes;
+pub mod session;
+pub mod snapshot;
+pub mod template;
 pub mod ws_handlers;
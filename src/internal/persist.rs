@@ -0,0 +1,320 @@
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, PrettyFormatter};
+use serde_json::{Map, Serializer, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, Notify, RwLock};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+// Mutations arriving within this window are coalesced into one write
+const PERSIST_DEBOUNCE_MS: u64 = 200;
+
+// Formatting of the source file, reproduced when writing data back
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonStyle {
+    pub indent: Option<String>,
+    pub trailing_newline: bool,
+}
+
+impl Default for JsonStyle {
+    fn default() -> Self {
+        JsonStyle {
+            indent: Some("  ".to_string()),
+            trailing_newline: true,
+        }
+    }
+}
+
+impl JsonStyle {
+    pub fn detect(content: &str) -> Self {
+        let trimmed = content.trim();
+        if !trimmed.contains('\n') {
+            return JsonStyle {
+                indent: None,
+                trailing_newline: content.ends_with('\n'),
+            };
+        }
+
+        // The first indented line tells us the unit of indentation
+        let indent = trimmed
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect::<String>()
+            })
+            .find(|ws| !ws.is_empty())
+            .unwrap_or_else(|| "  ".to_string());
+
+        JsonStyle {
+            indent: Some(indent),
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    pub fn render(&self, value: &Value) -> Result<Vec<u8>, serde_json::Error> {
+        let mut out = Vec::new();
+        match &self.indent {
+            Some(indent) => {
+                let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+                value.serialize(&mut Serializer::with_formatter(&mut out, formatter))?;
+            }
+            None => {
+                value.serialize(&mut Serializer::with_formatter(&mut out, CompactFormatter))?;
+            }
+        }
+        if self.trailing_newline {
+            out.push(b'\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Where mutations should be written for `--persist`: the source file itself, or a
+/// `<name>.data.json` sidecar when the data was generated from a schema or CSV.
+pub fn sidecar_path(source: &str) -> PathBuf {
+    let path = Path::new(source);
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("chimera");
+    path.with_file_name(format!("{}.data.json", stem))
+}
+
+/// Top-level keys of the data as read, taken before `$routes` and `$recordings`
+/// are removed from it.
+pub fn top_level_keys(data: &Value) -> Vec<String> {
+    data.as_object()
+        .map(|data| data.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub struct Persister {
+    target: PathBuf,
+    style: JsonStyle,
+    json_value: Arc<RwLock<Value>>,
+    dirty: Notify,
+    last_written: Mutex<Option<u64>>,
+    // Top-level keys kept out of the store (e.g. `$routes`) that must survive a write
+    preserved: Mutex<Map<String, Value>>,
+    // Top-level keys in the order the data file had them, reproduced on write
+    key_order: Mutex<Vec<String>>,
+    // Held for a whole flush, so the debounce worker and a shutdown flush don't
+    // race on the temp file
+    writing: AsyncMutex<()>,
+    logs_disabled: bool,
+}
+
+impl Persister {
+    pub fn spawn(
+        json_value: Arc<RwLock<Value>>,
+        target: PathBuf,
+        style: JsonStyle,
+        logs_disabled: bool,
+    ) -> Arc<Self> {
+        let persister = Arc::new(Persister {
+            target,
+            style,
+            json_value,
+            dirty: Notify::new(),
+            last_written: Mutex::new(None),
+            preserved: Mutex::new(Map::new()),
+            key_order: Mutex::new(Vec::new()),
+            writing: AsyncMutex::new(()),
+            logs_disabled,
+        });
+
+        let worker = persister.clone();
+        tokio::spawn(async move {
+            loop {
+                worker.dirty.notified().await;
+                sleep(Duration::from_millis(PERSIST_DEBOUNCE_MS)).await;
+                worker.flush().await;
+            }
+        });
+
+        if !logs_disabled {
+            info!("Persisting changes to {}", persister.target.display());
+        }
+        persister
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    // Hash of the last content written, so the file watcher can skip our own writes
+    pub fn last_written(&self) -> Option<u64> {
+        self.last_written.lock().ok().and_then(|hash| *hash)
+    }
+
+    pub fn preserve(&self, key: &str, value: Option<Value>) {
+        if let Ok(mut preserved) = self.preserved.lock() {
+            match value {
+                Some(value) => preserved.insert(key.to_string(), value),
+                None => preserved.remove(key),
+            };
+        }
+    }
+
+    pub fn remember_order(&self, keys: Vec<String>) {
+        if let Ok(mut key_order) = self.key_order.lock() {
+            *key_order = keys;
+        }
+    }
+
+    // Ask for a write; the background task batches bursts of mutations
+    pub fn schedule(&self) {
+        self.dirty.notify_one();
+    }
+
+    pub async fn flush(&self) {
+        let _writing = self.writing.lock().await;
+        let preserved = self
+            .preserved
+            .lock()
+            .map(|preserved| preserved.clone())
+            .unwrap_or_default();
+        let key_order = self
+            .key_order
+            .lock()
+            .map(|key_order| key_order.clone())
+            .unwrap_or_default();
+        let rendered = {
+            let data = self.json_value.read().await;
+            match data.as_object() {
+                Some(records) => self
+                    .style
+                    .render(&Value::Object(ordered(&key_order, &preserved, records))),
+                None => self.style.render(&data),
+            }
+        };
+
+        let bytes = match rendered {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!(
+                    "Failed to serialize data for {}: {}",
+                    self.target.display(),
+                    e
+                );
+                return;
+            }
+        };
+
+        if let Ok(mut hash) = self.last_written.lock() {
+            *hash = Some(content_hash(&bytes));
+        }
+
+        if let Err(e) = write_atomic(&self.target, &bytes).await {
+            error!("Failed to persist data to {}: {}", self.target.display(), e);
+        } else if !self.logs_disabled {
+            info!(
+                bytes = bytes.len(),
+                "Persisted data to {}",
+                self.target.display()
+            );
+        }
+    }
+}
+
+// Keys the file already had keep their place; new ones follow, preserved keys first
+fn ordered(
+    key_order: &[String],
+    preserved: &Map<String, Value>,
+    records: &Map<String, Value>,
+) -> Map<String, Value> {
+    let mut out = Map::new();
+    for key in key_order {
+        if let Some(value) = preserved.get(key).or_else(|| records.get(key)) {
+            out.insert(key.clone(), value.clone());
+        }
+    }
+    for (key, value) in preserved.iter().chain(records) {
+        if !out.contains_key(key) {
+            out.insert(key.clone(), value.clone());
+        }
+    }
+    out
+}
+
+// Write next to the target first so the rename stays on the same filesystem
+async fn write_atomic(target: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let file_name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("chimera.json");
+    let tmp = target.with_file_name(format!(".{}.tmp", file_name));
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, target).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn detects_indent_and_trailing_newline() {
+        let style = JsonStyle::detect("{\n    \"users\": []\n}\n");
+        assert_eq!(style.indent.as_deref(), Some("    "));
+        assert!(style.trailing_newline);
+
+        let style = JsonStyle::detect("{\"users\":[]}");
+        assert_eq!(style.indent, None);
+        assert!(!style.trailing_newline);
+    }
+
+    #[test]
+    fn renders_in_the_detected_style() {
+        let style = JsonStyle::detect("{\"a\":1}\n");
+        assert_eq!(style.render(&json!({"a": 1})).unwrap(), b"{\"a\":1}\n");
+    }
+
+    #[test]
+    fn sidecar_sits_next_to_the_source() {
+        assert_eq!(
+            sidecar_path("fixtures/schema.json"),
+            PathBuf::from("fixtures/schema.data.json")
+        );
+    }
+
+    #[test]
+    fn keeps_the_file_key_order() {
+        let key_order: Vec<String> = ["users", "$routes", "posts"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        let preserved = object(json!({"$routes": {}}));
+        let records = object(json!({"posts": [], "comments": [], "users": []}));
+
+        let keys: Vec<String> = ordered(&key_order, &preserved, &records)
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(keys, ["users", "$routes", "posts", "comments"]);
+    }
+
+    #[test]
+    fn drops_collections_that_are_gone() {
+        let key_order = vec!["users".to_string(), "posts".to_string()];
+        let records = object(json!({"posts": []}));
+
+        let out = ordered(&key_order, &Map::new(), &records);
+        assert_eq!(out.keys().collect::<Vec<_>>(), ["posts"]);
+    }
+}
@@ -19,9 +19,6 @@ fn is_port_ok(port: u16) -> bool {
             warn!("Port {} is busy 🚧", port);
             false
         }
-        Err(_) => match TcpListener::bind(&address) {
-            Ok(_) => true,
-            Err(_) => false,
-        },
+        Err(_) => TcpListener::bind(&address).is_ok(),
     }
 }
@@ -0,0 +1,495 @@
+use crate::internal::helpers::error_response;
+use crate::internal::persist::Persister;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+// Reserved top-level key in a fixture that holds the recorded exchanges
+pub const RECORDINGS_KEY: &str = "$recordings";
+
+const MAX_PROXY_BODY_BYTES: usize = 16 * 1024 * 1024;
+const UPSTREAM_TIMEOUT_SECS: u64 = 30;
+
+// Connection-level headers that must not be forwarded or replayed
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Which parts of a request must match a recording for it to be replayed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchOn {
+    Method,
+    Path,
+    Query,
+    Body,
+}
+
+pub const DEFAULT_MATCH_ON: &[MatchOn] = &[MatchOn::Method, MatchOn::Path, MatchOn::Query];
+
+impl MatchOn {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.trim().to_string())).ok()
+    }
+}
+
+/// Settings for the `proxy` subcommand.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    // Upstream to forward to; without one, only recordings are served
+    pub target: Option<String>,
+    // Answer from recordings first, forwarding only what hasn't been recorded
+    pub replay: Option<bool>,
+    #[serde(rename = "match")]
+    pub match_on: Option<Vec<MatchOn>>,
+}
+
+/// One recorded request/response pair.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recording {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_hash: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Value,
+}
+
+// The parts of an incoming request that recordings are matched on
+struct RequestKey {
+    method: String,
+    path: String,
+    query: Option<String>,
+    body_hash: Option<String>,
+}
+
+impl RequestKey {
+    fn new(method: &Method, path: &str, query: Option<&str>, body: &[u8]) -> Self {
+        RequestKey {
+            method: method.as_str().to_string(),
+            path: path.to_string(),
+            query: query.and_then(normalize_query),
+            body_hash: (!body.is_empty()).then(|| hex(&Sha256::digest(body))),
+        }
+    }
+
+    fn matches(&self, recording: &Recording, match_on: &[MatchOn]) -> bool {
+        match_on.iter().all(|rule| match rule {
+            MatchOn::Method => recording.method.eq_ignore_ascii_case(&self.method),
+            MatchOn::Path => recording.path == self.path,
+            MatchOn::Query => recording.query == self.query,
+            MatchOn::Body => recording.body_hash == self.body_hash,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// `b=2&a=1` and `a=1&b=2` are the same query
+fn normalize_query(query: &str) -> Option<String> {
+    let mut pairs: Vec<(String, String)> = serde_urlencoded::from_str(query).ok()?;
+    if pairs.is_empty() {
+        return None;
+    }
+    pairs.sort();
+    serde_urlencoded::to_string(pairs).ok()
+}
+
+fn is_json(headers: &BTreeMap<String, String>) -> bool {
+    headers
+        .get(header::CONTENT_TYPE.as_str())
+        .is_some_and(|content_type| content_type.contains("json"))
+}
+
+impl Recording {
+    fn response(&self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        // JSON bodies are stored as JSON; everything else as text
+        let bytes = match &self.body {
+            Value::Null => Vec::new(),
+            Value::String(text) if !is_json(&self.headers) => text.clone().into_bytes(),
+            other => other.to_string().into_bytes(),
+        };
+        let mut response = (status, Body::from(bytes)).into_response();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Recordings from a fixture's `$recordings` key, which groups them by route.
+pub fn take_recordings(data: &mut Value) -> Result<Vec<Recording>, String> {
+    let Some(grouped) = data
+        .as_object_mut()
+        .and_then(|obj| obj.remove(RECORDINGS_KEY))
+    else {
+        return Ok(Vec::new());
+    };
+    serde_json::from_value::<Map<String, Value>>(grouped)
+        .and_then(|grouped| {
+            grouped
+                .into_iter()
+                .map(|(_, recordings)| serde_json::from_value::<Vec<Recording>>(recordings))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map(|grouped| grouped.into_iter().flatten().collect())
+        .map_err(|e| format!("Invalid `{}` in data file: {}", RECORDINGS_KEY, e))
+}
+
+fn route_of(path: &str) -> String {
+    path.trim_matches('/').to_string()
+}
+
+/// The `$recordings` value for a fixture, grouped by route.
+pub fn group_by_route(recordings: &[Recording]) -> Value {
+    let mut grouped = Map::new();
+    for recording in recordings {
+        let entry = grouped
+            .entry(route_of(&recording.path))
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let (Value::Array(items), Ok(value)) = (entry, serde_json::to_value(recording)) {
+            items.push(value);
+        }
+    }
+    Value::Object(grouped)
+}
+
+pub struct ProxyState {
+    pub target: Option<String>,
+    pub replay: bool,
+    pub match_on: Vec<MatchOn>,
+    pub client: reqwest::Client,
+    pub recordings: Mutex<Vec<Recording>>,
+    // Route data from plain GETs, written to the fixture next to the recordings
+    pub json_value: Arc<RwLock<Value>>,
+    pub persister: Arc<Persister>,
+    pub logs_disabled: bool,
+}
+
+impl ProxyState {
+    pub fn client() -> reqwest::Client {
+        // Redirects are recorded as they are, not followed
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(Duration::from_secs(UPSTREAM_TIMEOUT_SECS))
+            .build()
+            .expect("HTTP client")
+    }
+
+    fn find(&self, key: &RequestKey) -> Option<Recording> {
+        let recordings = self.recordings.lock().ok()?;
+        recordings
+            .iter()
+            .rev()
+            .find(|recording| key.matches(recording, &self.match_on))
+            .cloned()
+    }
+
+    // Keep the newest recording for each request, then write the fixture
+    async fn record(&self, key: &RequestKey, recording: Recording) {
+        let grouped = {
+            let Ok(mut recordings) = self.recordings.lock() else {
+                return;
+            };
+            recordings.retain(|existing| !key.matches(existing, &self.match_on));
+            recordings.push(recording.clone());
+            group_by_route(&recordings)
+        };
+        self.persister.preserve(RECORDINGS_KEY, Some(grouped));
+
+        // Plain GETs also become data, so the fixture works as a data file
+        let route = route_of(&recording.path);
+        let plain_get = recording.method == "GET" && recording.query.is_none();
+        let succeeded = (200..300).contains(&recording.status);
+        if plain_get && succeeded && !route.is_empty() && is_json(&recording.headers) {
+            let mut data = self.json_value.write().await;
+            if let Some(data) = data.as_object_mut() {
+                data.insert(route, recording.body);
+            }
+        }
+        self.persister.schedule();
+    }
+
+    fn log(&self, status: StatusCode, method: &Method, path: &str, source: &str, start: Instant) {
+        if !self.logs_disabled {
+            info!(
+                date_time = Local::now().format("%Y/%m/%d - %H:%M:%S").to_string(),
+                status = status.as_str(),
+                method = method.as_str(),
+                path = path,
+                source = source,
+                elapsed_ms = start.elapsed().as_millis(),
+                "HTTP request"
+            );
+        }
+    }
+}
+
+fn forwarded_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = headers.clone();
+    for name in HOP_BY_HOP {
+        forwarded.remove(*name);
+    }
+    forwarded.remove(header::HOST);
+    forwarded.remove(header::CONTENT_LENGTH);
+    // Ask for plain bodies so they can be stored as JSON / text
+    forwarded.remove(header::ACCEPT_ENCODING);
+    forwarded
+}
+
+// Response headers worth replaying; CORS headers come from the proxy's own layer
+fn recorded_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            !HOP_BY_HOP.contains(&name)
+                && !name.starts_with("access-control-")
+                && !matches!(name, "content-length" | "date" | "vary")
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Forward to the target and record the exchange, or answer from recordings when
+/// replaying.
+pub async fn proxy_request(State(state): State<Arc<ProxyState>>, request: Request) -> Response {
+    let start = Instant::now();
+    let (parts, body) = request.into_parts();
+    let path = parts.uri.path().to_string();
+    let Ok(body) = to_bytes(body, MAX_PROXY_BODY_BYTES).await else {
+        return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+    };
+    let key = RequestKey::new(&parts.method, &path, parts.uri.query(), &body);
+
+    if state.replay {
+        if let Some(recording) = state.find(&key) {
+            let response = recording.response();
+            state.log(response.status(), &parts.method, &path, "replay", start);
+            return response;
+        }
+    }
+    let Some(target) = &state.target else {
+        state.log(StatusCode::NOT_FOUND, &parts.method, &path, "replay", start);
+        return error_response(
+            StatusCode::NOT_FOUND,
+            format!("No recording matches {} {}", parts.method, parts.uri),
+        );
+    };
+
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let url = format!("{}{}", target.trim_end_matches('/'), path_and_query);
+    let upstream = state
+        .client
+        .request(parts.method.clone(), &url)
+        .headers(forwarded_headers(&parts.headers))
+        .body(body)
+        .send()
+        .await;
+    let upstream = match upstream {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            warn!(url = url.as_str(), error = %e, "Proxy target unreachable");
+            state.log(
+                StatusCode::BAD_GATEWAY,
+                &parts.method,
+                &path,
+                "target",
+                start,
+            );
+            return error_response(StatusCode::BAD_GATEWAY, "Proxy target unreachable");
+        }
+    };
+
+    let status = upstream.status();
+    let headers = upstream.headers().clone();
+    let bytes: Bytes = match upstream.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(url = url.as_str(), error = %e, "Failed to read proxy response");
+            state.log(
+                StatusCode::BAD_GATEWAY,
+                &parts.method,
+                &path,
+                "target",
+                start,
+            );
+            return error_response(StatusCode::BAD_GATEWAY, "Failed to read proxy response");
+        }
+    };
+
+    let recorded = recorded_headers(&headers);
+    let body = if bytes.is_empty() {
+        Some(Value::Null)
+    } else if is_json(&recorded) {
+        serde_json::from_slice(&bytes).ok()
+    } else {
+        std::str::from_utf8(&bytes)
+            .ok()
+            .map(|text| Value::String(text.to_string()))
+    };
+    match body {
+        Some(body) => {
+            let recording = Recording {
+                method: key.method.clone(),
+                path: key.path.clone(),
+                query: key.query.clone(),
+                body_hash: key.body_hash.clone(),
+                status: status.as_u16(),
+                headers: recorded,
+                body,
+            };
+            state.record(&key, recording).await;
+        }
+        None => warn!(path = path.as_str(), "Not recording a binary response body"),
+    }
+
+    let mut response = (status, Body::from(bytes)).into_response();
+    for (name, value) in headers.iter() {
+        if !HOP_BY_HOP.contains(&name.as_str()) && name != header::CONTENT_LENGTH {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    state.log(status, &parts.method, &path, "target", start);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn recording(method: &str, path: &str, query: Option<&str>) -> Recording {
+        Recording {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.map(str::to_string),
+            body_hash: None,
+            status: 200,
+            headers: BTreeMap::new(),
+            body: Value::Null,
+        }
+    }
+
+    #[test]
+    fn parses_match_rules() {
+        assert_eq!(MatchOn::parse(" body "), Some(MatchOn::Body));
+        assert_eq!(MatchOn::parse("headers"), None);
+    }
+
+    #[test]
+    fn queries_match_in_any_order() {
+        let key = RequestKey::new(&Method::GET, "/users", Some("b=2&a=1"), b"");
+        let recorded = recording("get", "/users", Some("a=1&b=2"));
+        assert!(key.matches(&recorded, DEFAULT_MATCH_ON));
+
+        let other = recording("GET", "/users", None);
+        assert!(!key.matches(&other, DEFAULT_MATCH_ON));
+        assert!(key.matches(&other, &[MatchOn::Method, MatchOn::Path]));
+        assert_eq!(normalize_query(""), None);
+    }
+
+    #[test]
+    fn bodies_match_by_hash() {
+        let key = RequestKey::new(&Method::POST, "/users", None, br#"{"a":1}"#);
+        let mut recorded = recording("POST", "/users", None);
+        assert!(!key.matches(&recorded, &[MatchOn::Body]));
+        recorded.body_hash = key.body_hash.clone();
+        assert!(key.matches(&recorded, &[MatchOn::Body]));
+    }
+
+    #[test]
+    fn recordings_group_by_route_and_back() {
+        let recordings = vec![
+            recording("GET", "/users", None),
+            recording("GET", "/users/1", None),
+            recording("POST", "/users/", None),
+        ];
+        let mut data = json!({"users": [], "$recordings": group_by_route(&recordings)});
+        assert_eq!(data["$recordings"]["users"].as_array().unwrap().len(), 2);
+
+        let taken = take_recordings(&mut data).unwrap();
+        assert_eq!(taken.len(), 3);
+        assert_eq!(data, json!({"users": []}));
+
+        let mut data = json!({"$recordings": {"users": [{"method": "GET"}]}});
+        assert!(take_recordings(&mut data).is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_json_and_text_bodies() {
+        let mut json_recording = recording("GET", "/users", None);
+        json_recording.status = 201;
+        json_recording
+            .headers
+            .insert("content-type".to_string(), "application/json".to_string());
+        json_recording.body = json!("quoted");
+        let response = json_recording.response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "\"quoted\"");
+
+        let mut text_recording = recording("GET", "/ping", None);
+        text_recording.body = json!("pong");
+        let body = to_bytes(text_recording.response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "pong");
+    }
+
+    #[test]
+    fn drops_connection_and_cors_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+        headers.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+        let recorded = recorded_headers(&headers);
+        assert_eq!(recorded.keys().collect::<Vec<_>>(), ["etag"]);
+
+        headers.insert(header::HOST, HeaderValue::from_static("localhost"));
+        let forwarded = forwarded_headers(&headers);
+        assert!(forwarded.get(header::HOST).is_none());
+        assert!(forwarded.get(header::CONNECTION).is_none());
+        assert!(forwarded.get(header::ETAG).is_some());
+    }
+}
@@ -0,0 +1,775 @@
+use crate::internal::helpers::compare_values;
+use rayon::slice::ParallelSliceMut;
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Query params that drive the GET pipeline and must never be treated as field filters
+pub const RESERVED_PARAMS: &[&str] = &[
+    "_page",
+    "_limit",
+    "_start",
+    "_end",
+    "_sort",
+    "_order",
+    "_nulls",
+    "_collate",
+    "_envelope",
+    "_embed",
+    "_expand",
+    "_fields",
+    "_exclude",
+    "q",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FilterOp {
+    Eq,
+    Ne,
+    Gte,
+    Lte,
+    Like,
+}
+
+// Resolve a dotted path (e.g. `address.city`) inside a record
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |current, segment| match current {
+            Value::Object(map) => map.get(segment),
+            Value::Array(arr) => segment.parse::<usize>().ok().and_then(|i| arr.get(i)),
+            _ => None,
+        })
+}
+
+// Render a scalar the same way it would appear in a query string
+pub fn value_as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn split_filter_key(key: &str) -> (&str, FilterOp) {
+    for (suffix, op) in [
+        ("_ne", FilterOp::Ne),
+        ("_gte", FilterOp::Gte),
+        ("_lte", FilterOp::Lte),
+        ("_like", FilterOp::Like),
+    ] {
+        if let Some(field) = key.strip_suffix(suffix) {
+            if !field.is_empty() {
+                return (field, op);
+            }
+        }
+    }
+    (key, FilterOp::Eq)
+}
+
+// Numbers compare numerically, everything else falls back to text
+fn compare_to_param(value: &Value, param: &str) -> Ordering {
+    if let (Some(a), Ok(b)) = (value.as_f64(), param.parse::<f64>()) {
+        return a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+    }
+    value_as_text(value).as_str().cmp(param)
+}
+
+/// Apply json-server style field filters (`field=value`, `field_ne`, `field_gte`,
+/// `field_lte`, `field_like`) to a collection. Repeated `field=value` params match
+/// any of the given values; every other condition must hold for a record to be kept.
+pub fn apply_filters(arr: &mut Vec<Value>, params: &[(String, String)]) -> Result<(), String> {
+    let mut equals: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut conditions = Vec::new();
+
+    for (key, raw) in params {
+        if RESERVED_PARAMS.contains(&key.as_str()) {
+            continue;
+        }
+        match split_filter_key(key) {
+            (field, FilterOp::Eq) => equals.entry(field).or_default().push(raw.as_str()),
+            (field, FilterOp::Like) => {
+                let pattern = RegexBuilder::new(raw)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("Invalid pattern for `{}`: {}", key, e))?;
+                conditions.push((field, FilterOp::Like, raw.as_str(), Some(pattern)));
+            }
+            (field, op) => conditions.push((field, op, raw.as_str(), None)),
+        }
+    }
+
+    if equals.is_empty() && conditions.is_empty() {
+        return Ok(());
+    }
+
+    arr.retain(|record| {
+        let equals_ok = equals.iter().all(|(field, wanted)| {
+            lookup(record, field).is_some_and(|v| wanted.contains(&value_as_text(v).as_str()))
+        });
+
+        equals_ok
+            && conditions.iter().all(|(field, op, raw, pattern)| {
+                let value = lookup(record, field);
+                match op {
+                    FilterOp::Ne => value.is_none_or(|v| value_as_text(v) != *raw),
+                    FilterOp::Gte => value.is_some_and(|v| {
+                        !v.is_null() && compare_to_param(v, raw) != Ordering::Less
+                    }),
+                    FilterOp::Lte => value.is_some_and(|v| {
+                        !v.is_null() && compare_to_param(v, raw) != Ordering::Greater
+                    }),
+                    FilterOp::Like => value.is_some_and(|v| {
+                        pattern
+                            .as_ref()
+                            .is_some_and(|re| re.is_match(&value_as_text(v)))
+                    }),
+                    FilterOp::Eq => true,
+                }
+            })
+    });
+
+    Ok(())
+}
+
+// Lower-cased text of every string inside `value`, walking nested objects and arrays
+fn collect_strings(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) => out.push(s.to_lowercase()),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, out)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, out)),
+        _ => {}
+    }
+}
+
+/// Full-text `?q=` search: keep records where the whole term, or else every word
+/// of it, appears case-insensitively in their string fields. A non-empty `fields`
+/// list (dotted paths) limits the search to those fields.
+pub fn apply_search(arr: &mut Vec<Value>, params: &[(String, String)], fields: &[String]) {
+    let Some(term) = param(params, "q")
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty())
+    else {
+        return;
+    };
+    let tokens: Vec<&str> = term.split_whitespace().collect();
+
+    arr.retain(|record| {
+        let mut texts = Vec::new();
+        if fields.is_empty() {
+            collect_strings(record, &mut texts);
+        } else {
+            for field in fields {
+                if let Some(value) = lookup(record, field) {
+                    collect_strings(value, &mut texts);
+                }
+            }
+        }
+
+        texts.iter().any(|text| text.contains(&term))
+            || tokens
+                .iter()
+                .all(|token| texts.iter().any(|text| text.contains(token)))
+    });
+}
+
+// `?_embed=a&_embed=b` and `?_embed=a,b` are equivalent
+pub fn list_param(params: &[(String, String)], name: &str) -> Vec<String> {
+    params
+        .iter()
+        .filter(|(key, _)| key == name)
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Sparse fieldsets: `_fields=id,address.city` keeps only those (dotted) paths,
+/// `_exclude=` drops paths. Both can be combined.
+#[derive(Debug, Default)]
+pub struct Projection {
+    fields: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Projection {
+    pub fn from_params(params: &[(String, String)]) -> Option<Self> {
+        let projection = Projection {
+            fields: list_param(params, "_fields"),
+            exclude: list_param(params, "_exclude"),
+        };
+        (!projection.fields.is_empty() || !projection.exclude.is_empty()).then_some(projection)
+    }
+
+    // Works on a collection or a single record
+    pub fn apply(&self, records: &mut Value) {
+        match records {
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply_one(item)),
+            record => self.apply_one(record),
+        }
+    }
+
+    fn apply_one(&self, record: &mut Value) {
+        let Value::Object(fields) = record else {
+            return;
+        };
+        if !self.fields.is_empty() {
+            let mut picked = Map::new();
+            for path in &self.fields {
+                pick_path(fields, &path.split('.').collect::<Vec<_>>(), &mut picked);
+            }
+            *fields = picked;
+        }
+        for path in &self.exclude {
+            remove_path(fields, &path.split('.').collect::<Vec<_>>());
+        }
+    }
+}
+
+fn pick_path(source: &Map<String, Value>, path: &[&str], out: &mut Map<String, Value>) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(value) = source.get(*first) else {
+        return;
+    };
+    match value {
+        _ if rest.is_empty() => {
+            out.insert(first.to_string(), value.clone());
+        }
+        Value::Object(inner) => {
+            if let Value::Object(nested) = out
+                .entry(first.to_string())
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                pick_path(inner, rest, nested);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_path(fields: &mut Map<String, Value>, path: &[&str]) {
+    match path {
+        [] => {}
+        [last] => {
+            fields.shift_remove(*last);
+        }
+        [first, rest @ ..] => {
+            if let Some(Value::Object(inner)) = fields.get_mut(*first) {
+                remove_path(inner, rest);
+            }
+        }
+    }
+}
+
+// Default page size when `_page` is given without `_limit` and no `--page` is set
+const DEFAULT_PAGE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageWindow {
+    pub total: usize,
+    pub start: usize,
+    pub end: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+}
+
+fn param<'a>(params: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+fn numeric_param(params: &[(String, String)], key: &str) -> Result<Option<usize>, String> {
+    param(params, key)
+        .map(|raw| {
+            raw.parse::<usize>()
+                .map_err(|_| format!("`{}` must be a non-negative integer", key))
+        })
+        .transpose()
+}
+
+/// Work out which slice of a collection to return. `_page`/`_limit` win over
+/// `_start`/`_end`; with neither, the `--page` default (if any) selects page 1.
+pub fn page_window(
+    params: &[(String, String)],
+    default_limit: u64,
+    total: usize,
+) -> Result<Option<PageWindow>, String> {
+    let page = numeric_param(params, "_page")?;
+    let limit = numeric_param(params, "_limit")?;
+    let start = numeric_param(params, "_start")?;
+    let end = numeric_param(params, "_end")?;
+
+    if page == Some(0) {
+        return Err("`_page` starts at 1".to_string());
+    }
+
+    if page.is_none() && (start.is_some() || end.is_some()) {
+        let start = start.unwrap_or(0).min(total);
+        let end = match (end, limit) {
+            (Some(end), _) => end,
+            (None, Some(limit)) => start.saturating_add(limit),
+            (None, None) => total,
+        }
+        .clamp(start, total);
+        return Ok(Some(PageWindow {
+            total,
+            start,
+            end,
+            page: None,
+            limit: None,
+            pages: None,
+        }));
+    }
+
+    let limit = match (page, limit) {
+        (_, Some(limit)) => limit,
+        (Some(_), None) if default_limit > 0 => default_limit as usize,
+        (Some(_), None) => DEFAULT_PAGE_SIZE,
+        (None, None) if default_limit > 0 => default_limit as usize,
+        (None, None) => return Ok(None),
+    };
+
+    let page = page.unwrap_or(1);
+    let pages = if limit == 0 {
+        1
+    } else {
+        total.div_ceil(limit).max(1)
+    };
+    let start = (page - 1).saturating_mul(limit).min(total);
+    let end = start.saturating_add(limit).min(total);
+
+    Ok(Some(PageWindow {
+        total,
+        start,
+        end,
+        page: Some(page),
+        limit: Some(limit),
+        pages: Some(pages),
+    }))
+}
+
+/// Build an RFC 5988 `Link` header (first/prev/next/last) for a paged response
+pub fn link_header(
+    base_url: &str,
+    params: &[(String, String)],
+    window: &PageWindow,
+) -> Option<String> {
+    let (page, limit, pages) = (window.page?, window.limit?, window.pages?);
+
+    let page_url = |target: usize| {
+        let mut query: Vec<(String, String)> = params
+            .iter()
+            .filter(|(k, _)| k != "_page" && k != "_limit")
+            .cloned()
+            .collect();
+        query.push(("_page".to_string(), target.to_string()));
+        query.push(("_limit".to_string(), limit.to_string()));
+        let encoded = serde_urlencoded::to_string(&query).unwrap_or_default();
+        format!("<{}?{}>", base_url, encoded)
+    };
+
+    let mut links = vec![format!("{}; rel=\"first\"", page_url(1))];
+    if page > 1 {
+        links.push(format!("{}; rel=\"prev\"", page_url((page - 1).min(pages))));
+    }
+    if page < pages {
+        links.push(format!("{}; rel=\"next\"", page_url(page + 1)));
+    }
+    links.push(format!("{}; rel=\"last\"", page_url(pages)));
+
+    Some(links.join(", "))
+}
+
+/// Whether the response should be wrapped as `{data, meta}`. `_envelope` on the
+/// request overrides the server-wide `--envelope` default.
+pub fn envelope_requested(params: &[(String, String)], default: bool) -> bool {
+    match param(params, "_envelope") {
+        Some(raw) => matches!(raw, "" | "1" | "true" | "yes"),
+        None => default,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NullsOrder {
+    First,
+    #[default]
+    Last,
+}
+
+impl NullsOrder {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.to_lowercase().as_str() {
+            "first" => Some(NullsOrder::First),
+            "last" => Some(NullsOrder::Last),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub field: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortSpec {
+    pub keys: Vec<SortKey>,
+    pub nulls: NullsOrder,
+    pub ignore_case: bool,
+}
+
+impl SortSpec {
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        for key in &self.keys {
+            let a_val = lookup(a, &key.field).filter(|v| !v.is_null());
+            let b_val = lookup(b, &key.field).filter(|v| !v.is_null());
+
+            // Nulls keep their configured position regardless of direction
+            let ordering = match (a_val, b_val) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) if self.nulls == NullsOrder::First => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) if self.nulls == NullsOrder::First => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(x), Some(y)) => {
+                    let ordering = compare_values(x, y, self.ignore_case);
+                    if key.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                }
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// Build the sort for a request from `_sort`/`_order` (comma separated, `-field`
+/// also means descending), `_nulls` and `_collate=nocase`. Without `_sort` the
+/// CLI `--sort` rule for the route is used.
+pub fn sort_spec(
+    params: &[(String, String)],
+    default_rule: Option<&(String, String)>,
+    default_nulls: NullsOrder,
+) -> Option<SortSpec> {
+    let keys = match param(params, "_sort") {
+        Some(raw) => {
+            let orders: Vec<&str> = param(params, "_order")
+                .map(|o| o.split(',').map(str::trim).collect())
+                .unwrap_or_default();
+            raw.split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .enumerate()
+                .map(|(i, field)| match field.strip_prefix('-') {
+                    Some(field) => SortKey {
+                        field: field.to_string(),
+                        descending: true,
+                    },
+                    None => SortKey {
+                        field: field.to_string(),
+                        descending: orders
+                            .get(i)
+                            .or(orders.last())
+                            .is_some_and(|o| o.eq_ignore_ascii_case("desc")),
+                    },
+                })
+                .collect::<Vec<_>>()
+        }
+        None => {
+            let (order, key) = default_rule?;
+            vec![SortKey {
+                field: key.clone(),
+                descending: order.eq_ignore_ascii_case("desc"),
+            }]
+        }
+    };
+
+    if keys.is_empty() {
+        return None;
+    }
+
+    Some(SortSpec {
+        keys,
+        nulls: param(params, "_nulls")
+            .and_then(NullsOrder::parse)
+            .unwrap_or(default_nulls),
+        ignore_case: param(params, "_collate").is_some_and(|c| c.eq_ignore_ascii_case("nocase")),
+    })
+}
+
+pub fn sort_records(arr: &mut [Value], spec: &SortSpec) {
+    if arr.len() > 1 {
+        arr.par_sort_by(|a, b| spec.compare(a, b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn ids(arr: &[Value]) -> Vec<i64> {
+        arr.iter().filter_map(|v| v["id"].as_i64()).collect()
+    }
+
+    fn users() -> Vec<Value> {
+        vec![
+            json!({"id": 1, "name": "Ada", "age": 36, "address": {"city": "London"}}),
+            json!({"id": 2, "name": "Alan", "age": 41, "address": {"city": "Wilmslow"}}),
+            json!({"id": 3, "name": "Grace", "age": null, "address": {"city": "New York"}}),
+        ]
+    }
+
+    #[test]
+    fn filters_by_equality_and_nested_paths() {
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("address.city", "London")])).unwrap();
+        assert_eq!(ids(&arr), [1]);
+
+        // Repeated equality params match any value
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("id", "1"), ("id", "3")])).unwrap();
+        assert_eq!(ids(&arr), [1, 3]);
+    }
+
+    #[test]
+    fn filters_by_operators() {
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("age_gte", "37")])).unwrap();
+        assert_eq!(ids(&arr), [2]);
+
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("age_lte", "41"), ("name_ne", "Alan")])).unwrap();
+        assert_eq!(ids(&arr), [1]);
+
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("name_like", "^a")])).unwrap();
+        assert_eq!(ids(&arr), [1, 2]);
+    }
+
+    #[test]
+    fn ignores_reserved_params_and_rejects_bad_patterns() {
+        let mut arr = users();
+        apply_filters(&mut arr, &params(&[("_page", "2"), ("_sort", "name")])).unwrap();
+        assert_eq!(arr.len(), 3);
+
+        assert!(apply_filters(&mut arr, &params(&[("name_like", "(")])).is_err());
+    }
+
+    #[test]
+    fn page_window_slices_pages() {
+        let window = page_window(&params(&[("_page", "2"), ("_limit", "10")]), 0, 25)
+            .unwrap()
+            .unwrap();
+        assert_eq!((window.start, window.end), (10, 20));
+        assert_eq!(window.pages, Some(3));
+
+        // Past the last page is empty rather than an error
+        let window = page_window(&params(&[("_page", "9"), ("_limit", "10")]), 0, 25)
+            .unwrap()
+            .unwrap();
+        assert_eq!((window.start, window.end), (25, 25));
+
+        let window = page_window(&params(&[("_page", "1")]), 0, 25)
+            .unwrap()
+            .unwrap();
+        assert_eq!(window.limit, Some(DEFAULT_PAGE_SIZE));
+    }
+
+    #[test]
+    fn page_window_defaults_and_ranges() {
+        assert!(page_window(&[], 0, 25).unwrap().is_none());
+        let window = page_window(&[], 5, 25).unwrap().unwrap();
+        assert_eq!((window.start, window.end, window.page), (0, 5, Some(1)));
+
+        let window = page_window(&params(&[("_start", "20"), ("_limit", "10")]), 0, 25)
+            .unwrap()
+            .unwrap();
+        assert_eq!((window.start, window.end, window.page), (20, 25, None));
+
+        let huge = usize::MAX.to_string();
+        let window = page_window(&params(&[("_start", "1"), ("_limit", &huge)]), 0, 25)
+            .unwrap()
+            .unwrap();
+        assert_eq!((window.start, window.end), (1, 25));
+    }
+
+    #[test]
+    fn page_window_rejects_bad_numbers() {
+        assert!(page_window(&params(&[("_page", "0")]), 0, 25).is_err());
+        assert!(page_window(&params(&[("_limit", "-1")]), 0, 25).is_err());
+    }
+
+    #[test]
+    fn link_header_points_at_neighbouring_pages() {
+        let query = params(&[("name", "a b"), ("_page", "2"), ("_limit", "10")]);
+        let window = page_window(&query, 0, 25).unwrap().unwrap();
+        let link = link_header("http://localhost/users", &query, &window).unwrap();
+        assert_eq!(
+            link,
+            "<http://localhost/users?name=a+b&_page=1&_limit=10>; rel=\"first\", \
+             <http://localhost/users?name=a+b&_page=1&_limit=10>; rel=\"prev\", \
+             <http://localhost/users?name=a+b&_page=3&_limit=10>; rel=\"next\", \
+             <http://localhost/users?name=a+b&_page=3&_limit=10>; rel=\"last\""
+        );
+
+        // `_start`/`_end` windows have no pages to link
+        let range = params(&[("_start", "0"), ("_end", "5")]);
+        let window = page_window(&range, 0, 25).unwrap().unwrap();
+        assert!(link_header("http://localhost/users", &[], &window).is_none());
+    }
+
+    #[test]
+    fn sort_spec_reads_keys_and_orders() {
+        let spec = sort_spec(
+            &params(&[("_sort", "name,-age,id"), ("_order", "desc")]),
+            None,
+            NullsOrder::Last,
+        )
+        .unwrap();
+        let keys: Vec<(&str, bool)> = spec
+            .keys
+            .iter()
+            .map(|key| (key.field.as_str(), key.descending))
+            .collect();
+        // The last `_order` carries over to the remaining keys
+        assert_eq!(keys, [("name", true), ("age", true), ("id", true)]);
+
+        let rule = ("desc".to_string(), "age".to_string());
+        let spec = sort_spec(&[], Some(&rule), NullsOrder::First).unwrap();
+        assert_eq!(spec.keys[0].field, "age");
+        assert!(spec.keys[0].descending);
+        assert_eq!(spec.nulls, NullsOrder::First);
+
+        assert!(sort_spec(&[], None, NullsOrder::Last).is_none());
+        assert!(sort_spec(&params(&[("_sort", " , ")]), None, NullsOrder::Last).is_none());
+    }
+
+    #[test]
+    fn sort_records_by_several_keys() {
+        let mut arr = vec![
+            json!({"id": 1, "team": "b", "score": 2}),
+            json!({"id": 2, "team": "a", "score": 1}),
+            json!({"id": 3, "team": "b", "score": 5}),
+            json!({"id": 4, "team": "a", "score": 9}),
+        ];
+        let spec = sort_spec(&params(&[("_sort", "team,-score")]), None, NullsOrder::Last).unwrap();
+        sort_records(&mut arr, &spec);
+        assert_eq!(ids(&arr), [4, 2, 3, 1]);
+    }
+
+    #[test]
+    fn nulls_keep_their_place_in_either_direction() {
+        for order in ["asc", "desc"] {
+            let mut arr = users();
+            let query = params(&[("_sort", "age"), ("_order", order), ("_nulls", "first")]);
+            let spec = sort_spec(&query, None, NullsOrder::Last).unwrap();
+            sort_records(&mut arr, &spec);
+            assert_eq!(ids(&arr)[0], 3);
+
+            let mut arr = users();
+            let spec = sort_spec(
+                &params(&[("_sort", "age"), ("_order", order)]),
+                None,
+                NullsOrder::Last,
+            )
+            .unwrap();
+            sort_records(&mut arr, &spec);
+            assert_eq!(ids(&arr)[2], 3);
+        }
+    }
+
+    #[test]
+    fn collate_nocase_ignores_case() {
+        let mut arr = vec![
+            json!({"id": 1, "name": "bob"}),
+            json!({"id": 2, "name": "Carol"}),
+        ];
+        let spec = sort_spec(&params(&[("_sort", "name")]), None, NullsOrder::Last).unwrap();
+        sort_records(&mut arr, &spec);
+        assert_eq!(ids(&arr), [2, 1]);
+
+        let query = params(&[("_sort", "name"), ("_collate", "nocase")]);
+        let spec = sort_spec(&query, None, NullsOrder::Last).unwrap();
+        sort_records(&mut arr, &spec);
+        assert_eq!(ids(&arr), [1, 2]);
+    }
+
+    #[test]
+    fn search_matches_the_phrase_or_every_word() {
+        let mut arr = users();
+        apply_search(&mut arr, &params(&[("q", "new york")]), &[]);
+        assert_eq!(ids(&arr), [3]);
+
+        // Words may be spread over several fields
+        let mut arr = users();
+        apply_search(&mut arr, &params(&[("q", "ADA london")]), &[]);
+        assert_eq!(ids(&arr), [1]);
+
+        let mut arr = users();
+        apply_search(&mut arr, &params(&[("q", "  ")]), &[]);
+        assert_eq!(arr.len(), 3);
+    }
+
+    #[test]
+    fn search_fields_limit_where_to_look() {
+        let fields = vec!["name".to_string()];
+        let mut arr = users();
+        apply_search(&mut arr, &params(&[("q", "london")]), &fields);
+        assert!(arr.is_empty());
+
+        let fields = vec!["address.city".to_string()];
+        let mut arr = users();
+        apply_search(&mut arr, &params(&[("q", "wilm")]), &fields);
+        assert_eq!(ids(&arr), [2]);
+    }
+
+    #[test]
+    fn projection_picks_and_drops_paths() {
+        let query = params(&[("_fields", "id,address.city"), ("_fields", "missing")]);
+        let projection = Projection::from_params(&query).unwrap();
+        let mut record = users()[0].clone();
+        projection.apply(&mut record);
+        assert_eq!(record, json!({"id": 1, "address": {"city": "London"}}));
+
+        let projection =
+            Projection::from_params(&params(&[("_exclude", "age, address.city")])).unwrap();
+        let mut records = Value::Array(users());
+        projection.apply(&mut records);
+        assert_eq!(records[1], json!({"id": 2, "name": "Alan", "address": {}}));
+    }
+
+    #[test]
+    fn projection_needs_a_field_list() {
+        assert!(Projection::from_params(&params(&[("_fields", " , ")])).is_none());
+        assert_eq!(
+            list_param(&params(&[("_embed", "a,b"), ("_embed", "c")]), "_embed"),
+            ["a", "b", "c"]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use crate::internal::chimera::AppState;
+use crate::internal::helpers::error_response;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::warn;
+
+const DEFAULT_WINDOW_SECS: u64 = 60;
+const DEFAULT_KEY_HEADER: &str = "x-api-key";
+// Full buckets are forgotten once this many clients are being tracked
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// What a bucket is kept per: the client's IP, a request header such as an API
+/// key, or one bucket for everybody.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum RateLimitKey {
+    Ip,
+    Global,
+    Header(String),
+}
+
+impl RateLimitKey {
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.trim() {
+            "ip" => Some(RateLimitKey::Ip),
+            "global" => Some(RateLimitKey::Global),
+            "header" | "api-key" | "api_key" => {
+                Some(RateLimitKey::Header(DEFAULT_KEY_HEADER.to_string()))
+            }
+            other => other
+                .strip_prefix("header:")
+                .filter(|name| !name.is_empty())
+                .map(|name| RateLimitKey::Header(name.to_ascii_lowercase())),
+        }
+    }
+}
+
+impl TryFrom<String> for RateLimitKey {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, String> {
+        RateLimitKey::parse(&spec).ok_or_else(|| {
+            format!(
+                "Invalid rate limit key `{}`. Use ip, global, api-key or header:<name>",
+                spec
+            )
+        })
+    }
+}
+
+impl From<RateLimitKey> for String {
+    fn from(key: RateLimitKey) -> Self {
+        match key {
+            RateLimitKey::Ip => "ip".to_string(),
+            RateLimitKey::Global => "global".to_string(),
+            RateLimitKey::Header(name) => format!("header:{}", name),
+        }
+    }
+}
+
+/// Token bucket settings, at the top level of the config file or under a route
+/// or method.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    // Requests allowed per `window` seconds
+    pub requests: Option<u32>,
+    pub window: Option<u64>,
+    // Bucket size, i.e. how many requests may arrive at once; defaults to `requests`
+    pub burst: Option<u32>,
+    pub by: Option<RateLimitKey>,
+}
+
+impl RateLimitConfig {
+    pub fn overlay(&mut self, other: &RateLimitConfig) {
+        self.requests = other.requests.or(self.requests);
+        self.window = other.window.or(self.window);
+        self.burst = other.burst.or(self.burst);
+        self.by = other.by.clone().or(self.by.take());
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requests.is_some_and(|requests| requests > 0)
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.or(self.requests).unwrap_or(0).max(1) as f64
+    }
+
+    // Tokens added back per second
+    fn refill_rate(&self) -> f64 {
+        let window = self.window.unwrap_or(DEFAULT_WINDOW_SECS).max(1) as f64;
+        self.requests.unwrap_or(0) as f64 / window
+    }
+
+    /// The bucket key for a request, from its headers and peer address.
+    pub fn client_key(&self, headers: &HeaderMap, addr: Option<SocketAddr>) -> String {
+        let ip = || addr.map(|addr| addr.ip().to_string()).unwrap_or_default();
+        match self.by.as_ref().unwrap_or(&RateLimitKey::Ip) {
+            RateLimitKey::Ip => ip(),
+            RateLimitKey::Global => String::new(),
+            // Requests without the header share their IP's bucket
+            RateLimitKey::Header(name) => headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(|value| format!("{}={}", name, value))
+                .unwrap_or_else(ip),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Outcome of taking a token: the `X-RateLimit-*` numbers, and when to retry.
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the bucket is full again
+    pub reset: u64,
+    // Seconds until the next request would be allowed
+    pub retry_after: u64,
+}
+
+impl Decision {
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(self.reset));
+        if !self.allowed {
+            headers.insert("retry-after", HeaderValue::from(self.retry_after));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Take a token from the bucket for `client` under `scope` (the route, or
+    /// empty for the global limit).
+    pub fn check(&self, scope: &str, client: &str, config: &RateLimitConfig) -> Decision {
+        let capacity = config.capacity();
+        let rate = config.refill_rate();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_BUCKETS {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity
+            });
+        }
+        let bucket = buckets
+            .entry((scope.to_string(), client.to_string()))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let seconds_until = |tokens: f64| {
+            if tokens <= 0.0 {
+                0
+            } else if rate > 0.0 {
+                (tokens / rate).ceil() as u64
+            } else {
+                u64::MAX
+            }
+        };
+
+        Decision {
+            allowed,
+            limit: capacity as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset: seconds_until(capacity - bucket.tokens),
+            retry_after: seconds_until(1.0 - bucket.tokens),
+        }
+    }
+
+    /// Drop the bucket for `client` under `scope`, e.g. when a WebSocket closes.
+    pub fn forget(&self, scope: &str, client: &str) {
+        self.buckets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&(scope.to_string(), client.to_string()));
+    }
+}
+
+/// Enforce the global and per-route rate limits, answering `429` with
+/// `Retry-After` once a client's bucket is empty.
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request.uri().path().trim_start_matches('/').to_string();
+    let method = request.method().clone();
+    let Some((scope, config)) = state.rate_limit_for(&route, method.as_str()) else {
+        return next.run(request).await;
+    };
+
+    let addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let client = config.client_key(request.headers(), addr);
+    let decision = state.rate_limiter.check(&scope, &client, &config);
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        if !state.logs_disabled {
+            warn!(
+                method = method.as_str(),
+                path = request.uri().path(),
+                client = client.as_str(),
+                retry_after = decision.retry_after,
+                "Rate limit exceeded"
+            );
+        }
+        error_response(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded")
+    };
+    decision.apply_headers(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(requests: u32, window: u64) -> RateLimitConfig {
+        RateLimitConfig {
+            requests: Some(requests),
+            window: Some(window),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(RateLimitKey::parse("ip"), Some(RateLimitKey::Ip));
+        assert_eq!(
+            RateLimitKey::parse("api-key"),
+            Some(RateLimitKey::Header("x-api-key".to_string()))
+        );
+        assert_eq!(
+            RateLimitKey::parse("header:X-Tenant"),
+            Some(RateLimitKey::Header("x-tenant".to_string()))
+        );
+        assert_eq!(RateLimitKey::parse("header:"), None);
+    }
+
+    #[test]
+    fn rejects_requests_past_the_burst() {
+        let limiter = RateLimiter::default();
+        let config = limit(2, 3600);
+
+        assert_eq!(limiter.check("", "a", &config).remaining, 1);
+        assert!(limiter.check("", "a", &config).allowed);
+        let decision = limiter.check("", "a", &config);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 1800);
+
+        // Other clients and scopes have buckets of their own
+        assert!(limiter.check("", "b", &config).allowed);
+        assert!(limiter.check("users", "a", &config).allowed);
+    }
+
+    #[test]
+    fn forgotten_clients_start_with_a_full_bucket() {
+        let limiter = RateLimiter::default();
+        let config = limit(1, 3600);
+
+        assert!(limiter.check("ws users", "conn", &config).allowed);
+        assert!(!limiter.check("ws users", "conn", &config).allowed);
+        limiter.forget("ws users", "conn");
+        assert!(limiter.check("ws users", "conn", &config).allowed);
+    }
+
+    #[test]
+    fn overlay_keeps_unset_fields() {
+        let mut config = limit(10, 60);
+        config.overlay(&RateLimitConfig {
+            burst: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(config.requests, Some(10));
+        assert_eq!(config.burst, Some(3));
+    }
+
+    #[test]
+    fn header_keys_fall_back_to_the_ip() {
+        let config = RateLimitConfig {
+            by: Some(RateLimitKey::Header("x-api-key".to_string())),
+            ..limit(1, 60)
+        };
+        let addr = Some(SocketAddr::from(([10, 0, 0, 1], 80)));
+        let mut headers = HeaderMap::new();
+        assert_eq!(config.client_key(&headers, addr), "10.0.0.1");
+        headers.insert("x-api-key", HeaderValue::from_static("k1"));
+        assert_eq!(config.client_key(&headers, addr), "x-api-key=k1");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::internal::query::value_as_text;
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+
+pub const DEFAULT_ID_FIELD: &str = "id";
+
+pub fn record_id(record: &Value, id_field: &str) -> Option<String> {
+    match record.get(id_field)? {
+        Value::Null => None,
+        id => Some(value_as_text(id)),
+    }
+}
+
+// Ids are compared as text, so `/users/7` finds both `7` and `"7"`
+pub fn matches_id(record: &Value, id_field: &str, id: &str) -> bool {
+    record_id(record, id_field).is_some_and(|record_id| record_id == id)
+}
+
+/// Decide whether the last segment of `route` is a record id. A path that is itself
+/// a key in the data is always a collection (so `api/v2` stays a route); otherwise
+/// the last segment is an id when its parent is a collection, or when it is numeric.
+/// Returns the collection and the id.
+pub fn split_record_path<'a>(data: &Value, route: &'a str) -> Option<(&'a str, &'a str)> {
+    let (collection, id) = route.rsplit_once('/')?;
+    if id.is_empty() || data.get(route).is_some() {
+        return None;
+    }
+    let parent_is_collection = data.get(collection).is_some_and(Value::is_array);
+    (parent_is_collection || id.parse::<u64>().is_ok()).then_some((collection, id))
+}
+
+// A collection whose records carry string ids gets UUIDs for new records
+fn uses_string_ids(records: &[Value], id_field: &str) -> bool {
+    records
+        .iter()
+        .filter_map(|record| record.get(id_field))
+        .any(Value::is_string)
+}
+
+fn max_numeric_id(records: &[Value], id_field: &str) -> u64 {
+    records
+        .iter()
+        .filter_map(|record| record.get(id_field)?.as_u64())
+        .max()
+        .unwrap_or(0)
+}
+
+/// The JSON value for an id taken from a path, typed like the collection's ids.
+pub fn id_value(records: &[Value], id_field: &str, id: &str) -> Value {
+    match id.parse::<u64>() {
+        Ok(number) if !uses_string_ids(records, id_field) => Value::Number(Number::from(number)),
+        _ => Value::String(id.to_string()),
+    }
+}
+
+// Put the id first, where fixtures usually have it
+fn with_id_first(fields: &mut Map<String, Value>, id_field: &str, id: Value) {
+    let mut with_id = Map::new();
+    with_id.insert(id_field.to_string(), id);
+    with_id.extend(
+        std::mem::take(fields)
+            .into_iter()
+            .filter(|(k, _)| k != id_field),
+    );
+    *fields = with_id;
+}
+
+// Set the record's id, keeping its position if the field is already there
+pub fn set_id(record: &mut Value, id_field: &str, id: Value) {
+    if let Value::Object(fields) = record {
+        match fields.get_mut(id_field) {
+            Some(existing) => *existing = id,
+            None => with_id_first(fields, id_field, id),
+        }
+    }
+}
+
+/// Give every object in `items` an id that doesn't clash with `records`: the next
+/// number for numeric collections, a UUID for string-keyed ones. Ids supplied by
+/// the client are kept, but an id that already exists is an error naming it.
+pub fn assign_ids(
+    records: &[Value],
+    mut items: Vec<Value>,
+    id_field: &str,
+) -> Result<Vec<Value>, String> {
+    let mut taken: HashSet<String> = records
+        .iter()
+        .filter_map(|record| record_id(record, id_field))
+        .collect();
+    let string_ids = uses_string_ids(records, id_field) || uses_string_ids(&items, id_field);
+    let mut next = max_numeric_id(records, id_field).max(max_numeric_id(&items, id_field));
+
+    for item in items.iter_mut() {
+        let Value::Object(fields) = item else {
+            continue;
+        };
+        let id = match fields.get(id_field) {
+            Some(id) if !id.is_null() => value_as_text(id),
+            _ => {
+                let id = if string_ids {
+                    Value::String(uuid::Uuid::new_v4().to_string())
+                } else {
+                    next += 1;
+                    Value::Number(Number::from(next))
+                };
+                let text = value_as_text(&id);
+                with_id_first(fields, id_field, id);
+                text
+            }
+        };
+        if !taken.insert(id.clone()) {
+            return Err(id);
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn assigns_the_next_numeric_id_first() {
+        let records = vec![json!({"id": 1}), json!({"id": 4})];
+        let items = vec![json!({"name": "a"}), json!({"name": "b", "id": 9})];
+
+        let assigned = assign_ids(&records, items, DEFAULT_ID_FIELD).unwrap();
+        assert_eq!(
+            assigned,
+            [
+                json!({"id": 10, "name": "a"}),
+                json!({"name": "b", "id": 9})
+            ]
+        );
+        let keys: Vec<&String> = assigned[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["id", "name"]);
+    }
+
+    #[test]
+    fn rejects_ids_that_are_taken() {
+        let records = vec![json!({"id": 1})];
+        assert_eq!(
+            assign_ids(&records, vec![json!({"id": "1"})], DEFAULT_ID_FIELD),
+            Err("1".to_string())
+        );
+        assert!(assign_ids(
+            &[],
+            vec![json!({"id": 2}), json!({"id": 2})],
+            DEFAULT_ID_FIELD
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn set_id_keeps_the_field_in_place() {
+        let mut record = json!({"name": "a", "id": 1});
+        set_id(&mut record, DEFAULT_ID_FIELD, json!(5));
+        assert_eq!(record, json!({"name": "a", "id": 5}));
+        let keys: Vec<&String> = record.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["name", "id"]);
+    }
+
+    #[test]
+    fn string_keyed_collections_get_uuids() {
+        let records = vec![json!({"uid": "a1"})];
+        let assigned = assign_ids(&records, vec![json!({"name": "b"})], "uid").unwrap();
+        let uid = assigned[0]["uid"].as_str().unwrap();
+        assert!(uuid::Uuid::parse_str(uid).is_ok());
+    }
+
+    #[test]
+    fn ids_match_as_text() {
+        assert!(matches_id(&json!({"id": 7}), DEFAULT_ID_FIELD, "7"));
+        assert!(matches_id(&json!({"id": "7"}), DEFAULT_ID_FIELD, "7"));
+        assert!(matches_id(&json!({"sku": "x-1"}), "sku", "x-1"));
+        assert!(!matches_id(&json!({"id": null}), DEFAULT_ID_FIELD, "null"));
+    }
+
+    #[test]
+    fn path_ids_are_typed_like_the_collection() {
+        assert_eq!(
+            id_value(&[json!({"id": 1})], DEFAULT_ID_FIELD, "7"),
+            json!(7)
+        );
+        assert_eq!(
+            id_value(&[json!({"id": "a"})], DEFAULT_ID_FIELD, "7"),
+            json!("7")
+        );
+        assert_eq!(id_value(&[], DEFAULT_ID_FIELD, "abc"), json!("abc"));
+    }
+
+    #[test]
+    fn splits_record_paths() {
+        let data = json!({"users": [], "api/2": []});
+        assert_eq!(
+            split_record_path(&data, "users/ada"),
+            Some(("users", "ada"))
+        );
+        assert_eq!(split_record_path(&data, "things/7"), Some(("things", "7")));
+        assert_eq!(split_record_path(&data, "api/2"), None);
+        assert_eq!(split_record_path(&data, "things/abc"), None);
+        assert_eq!(split_record_path(&data, "users"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::internal::query::value_as_text;
+use serde_json::{Map, Value};
+
+// `comments` -> `comment`, `categories` -> `category`
+pub fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if let Some(stem) = name.strip_suffix("sses") {
+        format!("{}ss", stem)
+    } else if let Some(stem) = name.strip_suffix('s').filter(|_| !name.ends_with("ss")) {
+        stem.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// Collections a singular name (`user`, `category`) may refer to, most likely first
+fn plural_candidates(name: &str) -> Vec<String> {
+    let mut candidates = vec![format!("{}s", name), format!("{}es", name)];
+    if let Some(stem) = name.strip_suffix('y') {
+        candidates.push(format!("{}ies", stem));
+    }
+    candidates.push(name.to_string());
+    candidates
+}
+
+/// Foreign key that points from `children` to records of `parent`: `postId` for
+/// `posts`, or `post_id` when the children use snake_case keys.
+pub fn foreign_key(parent: &str, children: &[Value]) -> String {
+    let name = singular(parent.rsplit('/').next().unwrap_or(parent));
+    let snake = format!("{}_id", name);
+    if children.iter().any(|child| child.get(&snake).is_some()) {
+        snake
+    } else {
+        format!("{}Id", name)
+    }
+}
+
+fn links_to(child: &Value, foreign_key: &str, id: &str) -> bool {
+    child
+        .get(foreign_key)
+        .is_some_and(|value| !value.is_null() && value_as_text(value) == id)
+}
+
+/// A `/{parent}/{id}/{child}` path where both `parent` and `child` are collections.
+pub struct NestedRoute<'a> {
+    pub parent: &'a str,
+    pub parent_id: &'a str,
+    pub child: &'a str,
+}
+
+impl<'a> NestedRoute<'a> {
+    pub fn parse(data: &Value, route: &'a str) -> Option<Self> {
+        if data.get(route).is_some() {
+            return None;
+        }
+        let (rest, child) = route.rsplit_once('/')?;
+        let (parent, parent_id) = rest.rsplit_once('/')?;
+        let is_collection = |key: &str| data.get(key).is_some_and(Value::is_array);
+        (is_collection(parent) && is_collection(child)).then_some(NestedRoute {
+            parent,
+            parent_id,
+            child,
+        })
+    }
+
+    pub fn foreign_key(&self, data: &Value) -> String {
+        let children = data.get(self.child).and_then(Value::as_array);
+        foreign_key(self.parent, children.map(Vec::as_slice).unwrap_or_default())
+    }
+
+    // Child records that point at the parent record
+    pub fn children(&self, data: &Value) -> Value {
+        let foreign_key = self.foreign_key(data);
+        let children = data
+            .get(self.child)
+            .and_then(Value::as_array)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter(|child| links_to(child, &foreign_key, self.parent_id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        Value::Array(children)
+    }
+
+    /// The value to store in the foreign key of new children, typed like the
+    /// parent's id when the parent record exists.
+    pub fn parent_key(&self, data: &Value, id_field: &str) -> Value {
+        data.get(self.parent)
+            .and_then(Value::as_array)
+            .and_then(|parents| {
+                parents
+                    .iter()
+                    .filter_map(|parent| parent.get(id_field))
+                    .find(|id| value_as_text(id) == self.parent_id)
+            })
+            .cloned()
+            .unwrap_or_else(|| match self.parent_id.parse::<u64>() {
+                Ok(number) => Value::from(number),
+                Err(_) => Value::String(self.parent_id.to_string()),
+            })
+    }
+}
+
+/// Copy the collections that `_embed` / `_expand` need, so they can be joined
+/// after the data lock has been released.
+pub fn related_collections(data: &Value, embeds: &[String], expands: &[String]) -> Value {
+    let mut related = Map::new();
+    let names = embeds
+        .iter()
+        .cloned()
+        .chain(expands.iter().flat_map(|name| plural_candidates(name)));
+    for name in names {
+        if let Some(collection) = data.get(&name).filter(|v| v.is_array()) {
+            related.insert(name, collection.clone());
+        }
+    }
+    Value::Object(related)
+}
+
+fn each_record(records: &mut Value, mut f: impl FnMut(&mut Map<String, Value>)) {
+    match records {
+        Value::Array(items) => items
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+            .for_each(f),
+        Value::Object(record) => f(record),
+        _ => {}
+    }
+}
+
+/// `?_embed=comments`: attach the child records that point at each record.
+pub fn embed(
+    records: &mut Value,
+    related: &Value,
+    collection: &str,
+    id_field: &str,
+    embeds: &[String],
+) {
+    for name in embeds {
+        let Some(children) = related.get(name).and_then(Value::as_array) else {
+            continue;
+        };
+        let foreign_key = foreign_key(collection, children);
+        each_record(records, |record| {
+            let Some(id) = record.get(id_field).map(value_as_text) else {
+                return;
+            };
+            let linked = children
+                .iter()
+                .filter(|child| links_to(child, &foreign_key, &id))
+                .cloned()
+                .collect();
+            record.insert(name.clone(), Value::Array(linked));
+        });
+    }
+}
+
+/// `?_expand=user`: attach the record that `userId` (or `user_id`) points at as `user`.
+pub fn expand(
+    records: &mut Value,
+    related: &Value,
+    expands: &[String],
+    id_field_for: impl Fn(&str) -> String,
+) {
+    for name in expands {
+        let Some((collection, parents)) = plural_candidates(name).into_iter().find_map(|c| {
+            let parents = related.get(&c)?.as_array()?;
+            Some((c, parents))
+        }) else {
+            continue;
+        };
+        let id_field = id_field_for(&collection);
+        let keys = [format!("{}Id", name), format!("{}_id", name)];
+
+        each_record(records, |record| {
+            let Some(id) = keys
+                .iter()
+                .find_map(|key| record.get(key).filter(|v| !v.is_null()))
+                .map(value_as_text)
+            else {
+                return;
+            };
+            if let Some(parent) = parents
+                .iter()
+                .find(|parent| parent.get(&id_field).map(value_as_text) == Some(id.clone()))
+            {
+                record.insert(name.clone(), parent.clone());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn blog() -> Value {
+        json!({
+            "users": [{"id": 1, "name": "Ada"}],
+            "posts": [{"id": 1, "userId": 1}, {"id": 2, "userId": 9}],
+            "comments": [
+                {"id": 1, "postId": 1},
+                {"id": 2, "postId": 2},
+                {"id": 3, "postId": "1"},
+            ],
+        })
+    }
+
+    #[test]
+    fn singular_and_foreign_keys() {
+        assert_eq!(singular("comments"), "comment");
+        assert_eq!(singular("categories"), "category");
+        assert_eq!(singular("classes"), "class");
+        assert_eq!(singular("glass"), "glass");
+
+        assert_eq!(foreign_key("posts", &[]), "postId");
+        assert_eq!(
+            foreign_key("api/posts", &[json!({"post_id": 1})]),
+            "post_id"
+        );
+    }
+
+    #[test]
+    fn nested_routes_list_linked_children() {
+        let data = blog();
+        let nested = NestedRoute::parse(&data, "posts/1/comments").unwrap();
+        assert_eq!(
+            nested.children(&data),
+            json!([{"id": 1, "postId": 1}, {"id": 3, "postId": "1"}])
+        );
+        assert_eq!(nested.parent_key(&data, "id"), json!(1));
+
+        assert!(NestedRoute::parse(&data, "posts/1/tags").is_none());
+        assert!(NestedRoute::parse(&data, "posts/1").is_none());
+    }
+
+    #[test]
+    fn parent_key_without_a_parent_record() {
+        let data = blog();
+        let by_number = NestedRoute::parse(&data, "posts/7/comments").unwrap();
+        assert_eq!(by_number.parent_key(&data, "id"), json!(7));
+        let by_name = NestedRoute::parse(&data, "posts/intro/comments").unwrap();
+        assert_eq!(by_name.parent_key(&data, "id"), json!("intro"));
+    }
+
+    #[test]
+    fn embeds_children_and_expands_parents() {
+        let data = blog();
+        let embeds = vec!["comments".to_string()];
+        let expands = vec!["user".to_string()];
+        let related = related_collections(&data, &embeds, &expands);
+
+        let mut posts = data["posts"].clone();
+        embed(&mut posts, &related, "posts", "id", &embeds);
+        expand(&mut posts, &related, &expands, |_| "id".to_string());
+
+        assert_eq!(posts[0]["comments"].as_array().unwrap().len(), 2);
+        assert_eq!(posts[0]["user"]["name"], "Ada");
+        // A dangling key is left alone
+        assert!(posts[1].get("user").is_none());
+    }
+
+    #[test]
+    fn expand_uses_the_parent_collection_id_field() {
+        let data = json!({
+            "users": [{"uid": "u1", "name": "Ada"}],
+            "posts": [{"id": 1, "userId": "u1"}],
+        });
+        let expands = vec!["user".to_string()];
+        let related = related_collections(&data, &[], &expands);
+
+        let mut post = data["posts"][0].clone();
+        expand(&mut post, &related, &expands, |collection| {
+            if collection == "users" { "uid" } else { "id" }.to_string()
+        });
+        assert_eq!(post["user"]["name"], "Ada");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::internal::loader::{file_extension, load_cors_origins, parse_data, CORS_FILE};
+use crate::internal::persist::{content_hash, top_level_keys, Persister};
+use crate::internal::proxy::{take_recordings, RECORDINGS_KEY};
+use crate::internal::routes::{take_route_definitions, RouteTable, SharedRoutes, ROUTES_KEY};
+use crate::internal::session::SessionStore;
+use crate::internal::snapshot::SnapshotStore;
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::{interval, Duration};
+use tracing::{error, info};
+
+// How often the data file and chimera.cors are checked for changes
+const WATCH_INTERVAL_MS: u64 = 500;
+
+pub type SharedOrigins = Arc<std::sync::RwLock<Vec<String>>>;
+
+// Outcome of the latest reload of the data file, `None` until one happens
+pub type SharedReloadStatus = Arc<std::sync::RwLock<Option<ReloadStatus>>>;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReloadStatus {
+    pub ok: bool,
+    pub at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Sent to subscribers (e.g. WebSocket connections) after a successful reload
+#[derive(Debug, Clone)]
+pub struct ReloadEvent {
+    pub path: String,
+}
+
+pub struct DataWatcher {
+    pub path: String,
+    pub auto_generate: bool,
+    pub json_value: Arc<RwLock<Value>>,
+    pub allowed_origins: Option<SharedOrigins>,
+    pub routes: SharedRoutes,
+    pub file_routes: RouteTable,
+    pub persister: Option<Arc<Persister>>,
+    // A reload becomes what `reset` returns to, and sessions start over from it
+    pub snapshots: Arc<SnapshotStore>,
+    pub sessions: Option<Arc<SessionStore>>,
+    pub reload_tx: broadcast::Sender<ReloadEvent>,
+    pub last_reload: SharedReloadStatus,
+    pub logs_disabled: bool,
+}
+
+async fn file_stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+impl DataWatcher {
+    pub fn spawn(self) {
+        if !self.logs_disabled {
+            info!("Watching {} for changes", self.path);
+        }
+        tokio::spawn(async move { self.run().await });
+    }
+
+    async fn run(self) {
+        let mut data_stamp = file_stamp(&self.path).await;
+        let mut cors_stamp = file_stamp(CORS_FILE).await;
+        let mut ticker = interval(Duration::from_millis(WATCH_INTERVAL_MS));
+
+        loop {
+            ticker.tick().await;
+
+            let stamp = file_stamp(&self.path).await;
+            if stamp.is_some() && stamp != data_stamp {
+                data_stamp = stamp;
+                self.reload_data().await;
+            }
+
+            if let Some(origins) = &self.allowed_origins {
+                let stamp = file_stamp(CORS_FILE).await;
+                if stamp != cors_stamp {
+                    cors_stamp = stamp;
+                    let reloaded = load_cors_origins().await.unwrap_or_default();
+                    if !self.logs_disabled {
+                        info!(origins = reloaded.len(), "Reloaded {}", CORS_FILE);
+                    }
+                    if let Ok(mut current) = origins.write() {
+                        *current = reloaded;
+                    }
+                }
+            }
+        }
+    }
+
+    fn record_reload(&self, error: Option<String>) {
+        let status = ReloadStatus {
+            ok: error.is_none(),
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            error,
+        };
+        if let Ok(mut last_reload) = self.last_reload.write() {
+            *last_reload = Some(status);
+        }
+    }
+
+    // Same parse/validate pipeline as startup; on failure the old data keeps serving
+    async fn reload_data(&self) {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) => {
+                error!(
+                    "Reload of {} failed, keeping previous data: {}",
+                    self.path, e
+                );
+                self.record_reload(Some(e.to_string()));
+                return;
+            }
+        };
+
+        // Our own --persist writes show up as changes too
+        if self
+            .persister
+            .as_ref()
+            .is_some_and(|p| p.last_written() == Some(content_hash(content.as_bytes())))
+        {
+            return;
+        }
+
+        let parsed = parse_data(&content, &file_extension(&self.path), self.auto_generate)
+            .and_then(|mut value| {
+                let key_order = top_level_keys(&value);
+                let definitions = value.get(ROUTES_KEY).cloned();
+                let recordings = value.get(RECORDINGS_KEY).cloned();
+                let routes = take_route_definitions(&mut value)?;
+                take_recordings(&mut value)?;
+                Ok((
+                    value,
+                    routes.merged(&self.file_routes),
+                    key_order,
+                    definitions,
+                    recordings,
+                ))
+            });
+
+        match parsed {
+            Ok((value, routes, key_order, definitions, recordings)) => {
+                if let Some(persister) = &self.persister {
+                    persister.remember_order(key_order);
+                    persister.preserve(ROUTES_KEY, definitions);
+                    persister.preserve(RECORDINGS_KEY, recordings);
+                }
+                self.snapshots.set_pristine(value.clone());
+                *self.json_value.write().await = value;
+                if let Some(sessions) = &self.sessions {
+                    sessions.clear();
+                }
+                if let Ok(mut current) = self.routes.write() {
+                    *current = Arc::new(routes);
+                }
+                if !self.logs_disabled {
+                    info!("Reloaded data from {}", self.path);
+                }
+                self.record_reload(None);
+                // No receivers just means no WebSocket clients are connected
+                let _ = self.reload_tx.send(ReloadEvent {
+                    path: self.path.clone(),
+                });
+            }
+            Err(e) => {
+                error!(
+                    "Reload of {} failed, keeping previous data: {}",
+                    self.path, e
+                );
+                self.record_reload(Some(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::routes::shared_routes;
+    use crate::internal::session::SessionConfig;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn data_file(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("chimera-{}-{}.json", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn watcher(path: &str, initial: Value) -> DataWatcher {
+        DataWatcher {
+            path: path.to_string(),
+            auto_generate: false,
+            json_value: Arc::new(RwLock::new(initial.clone())),
+            allowed_origins: None,
+            routes: shared_routes(RouteTable::default()),
+            file_routes: RouteTable::default(),
+            persister: None,
+            snapshots: Arc::new(SnapshotStore::new(initial, BTreeMap::new(), None)),
+            sessions: Some(Arc::new(SessionStore::new(&SessionConfig::default()))),
+            reload_tx: broadcast::channel(1).0,
+            last_reload: Default::default(),
+            logs_disabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_replaces_data_reset_point_and_sessions() {
+        let path = data_file("reload", r#"{"users": [{"id": 2}]}"#);
+        let watcher = watcher(path.to_str().unwrap(), json!({"users": [{"id": 1}]}));
+        let sessions = watcher.sessions.clone().unwrap();
+        sessions
+            .dataset("a", &Arc::new(RwLock::new(json!({}))), true)
+            .await;
+        let mut events = watcher.reload_tx.subscribe();
+
+        watcher.reload_data().await;
+        std::fs::remove_file(&path).unwrap();
+
+        let reloaded = json!({"users": [{"id": 2}]});
+        assert_eq!(*watcher.json_value.read().await, reloaded);
+        assert_eq!(watcher.snapshots.pristine().await, reloaded);
+        assert!(sessions.list().is_empty());
+        assert!(watcher.last_reload.read().unwrap().as_ref().unwrap().ok);
+        assert!(events.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_reload_keeps_serving_the_old_data() {
+        let path = data_file("broken", "{\"users\": [");
+        let initial = json!({"users": [{"id": 1}]});
+        let watcher = watcher(path.to_str().unwrap(), initial.clone());
+
+        watcher.reload_data().await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(*watcher.json_value.read().await, initial);
+        assert_eq!(watcher.snapshots.pristine().await, initial);
+        let status = watcher.last_reload.read().unwrap().clone().unwrap();
+        assert!(!status.ok);
+        assert!(status.error.is_some());
+    }
+}
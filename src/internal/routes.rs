@@ -0,0 +1,548 @@
+use crate::internal::auth::AuthScheme;
+use crate::internal::chaos::ChaosConfig;
+use crate::internal::chimera::AppState;
+use crate::internal::latency::Latency;
+use crate::internal::rate_limit::RateLimitConfig;
+use crate::internal::template::RequestContext;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+// Request bodies larger than this aren't buffered for templating
+const MAX_TEMPLATE_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// Reserved top-level key in the data file that holds route definitions
+pub const ROUTES_KEY: &str = "$routes";
+
+// Response overrides for a route, or for one method on it
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MethodConfig {
+    pub latency: Option<Latency>,
+    // Response bandwidth in bytes/sec
+    pub bandwidth: Option<u64>,
+    pub status: Option<u16>,
+    pub headers: HashMap<String, String>,
+    pub content_type: Option<String>,
+    pub body: Option<Value>,
+    // Render `{{...}}` placeholders in the body and headers on every request
+    pub dynamic: Option<bool>,
+    pub chaos: Option<ChaosConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub auth: Option<AuthScheme>,
+    // Roles (the user's `role` or the token's `role` claim) allowed in
+    pub roles: Option<Vec<String>>,
+}
+
+// Per-route overrides from the config or data file, keyed by route pattern
+// (`users`, `users/:id`, `api/*`). A collection key also covers its records.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteConfig {
+    pub latency: Option<Latency>,
+    pub bandwidth: Option<u64>,
+    pub page: Option<u64>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub id_field: Option<String>,
+    pub search_fields: Option<Vec<String>>,
+    pub status: Option<u16>,
+    pub headers: HashMap<String, String>,
+    pub content_type: Option<String>,
+    pub body: Option<Value>,
+    pub dynamic: Option<bool>,
+    pub chaos: Option<ChaosConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub auth: Option<AuthScheme>,
+    pub roles: Option<Vec<String>>,
+    pub get: Option<MethodConfig>,
+    pub post: Option<MethodConfig>,
+    pub put: Option<MethodConfig>,
+    pub patch: Option<MethodConfig>,
+    pub delete: Option<MethodConfig>,
+}
+
+impl MethodConfig {
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic.unwrap_or(false)
+    }
+
+    fn overlay(&mut self, other: &MethodConfig) {
+        self.latency = other.latency.or(self.latency);
+        self.bandwidth = other.bandwidth.or(self.bandwidth);
+        self.status = other.status.or(self.status);
+        self.headers.extend(other.headers.clone());
+        self.content_type = other.content_type.clone().or(self.content_type.take());
+        self.body = other.body.clone().or(self.body.take());
+        self.dynamic = other.dynamic.or(self.dynamic);
+        overlay_chaos(&mut self.chaos, &other.chaos);
+        overlay_rate_limit(&mut self.rate_limit, &other.rate_limit);
+        self.auth = other.auth.or(self.auth);
+        self.roles = other.roles.clone().or(self.roles.take());
+    }
+}
+
+fn overlay_chaos(base: &mut Option<ChaosConfig>, other: &Option<ChaosConfig>) {
+    if let Some(other) = other {
+        base.get_or_insert_with(ChaosConfig::default).overlay(other);
+    }
+}
+
+fn overlay_rate_limit(base: &mut Option<RateLimitConfig>, other: &Option<RateLimitConfig>) {
+    if let Some(other) = other {
+        base.get_or_insert_with(RateLimitConfig::default)
+            .overlay(other);
+    }
+}
+
+fn overlay_method(base: &mut Option<MethodConfig>, other: &Option<MethodConfig>) {
+    if let Some(other) = other {
+        base.get_or_insert_with(MethodConfig::default)
+            .overlay(other);
+    }
+}
+
+impl RouteConfig {
+    // Settings present in `other` win; headers are combined
+    pub fn overlay(&mut self, other: &RouteConfig) {
+        self.latency = other.latency.or(self.latency);
+        self.bandwidth = other.bandwidth.or(self.bandwidth);
+        self.page = other.page.or(self.page);
+        self.sort = other.sort.clone().or(self.sort.take());
+        self.order = other.order.clone().or(self.order.take());
+        self.id_field = other.id_field.clone().or(self.id_field.take());
+        self.search_fields = other.search_fields.clone().or(self.search_fields.take());
+        self.status = other.status.or(self.status);
+        self.headers.extend(other.headers.clone());
+        self.content_type = other.content_type.clone().or(self.content_type.take());
+        self.body = other.body.clone().or(self.body.take());
+        self.dynamic = other.dynamic.or(self.dynamic);
+        overlay_chaos(&mut self.chaos, &other.chaos);
+        overlay_rate_limit(&mut self.rate_limit, &other.rate_limit);
+        self.auth = other.auth.or(self.auth);
+        self.roles = other.roles.clone().or(self.roles.take());
+        overlay_method(&mut self.get, &other.get);
+        overlay_method(&mut self.post, &other.post);
+        overlay_method(&mut self.put, &other.put);
+        overlay_method(&mut self.patch, &other.patch);
+        overlay_method(&mut self.delete, &other.delete);
+    }
+
+    pub fn method(&self, method: &str) -> Option<&MethodConfig> {
+        match method.to_ascii_uppercase().as_str() {
+            "GET" => self.get.as_ref(),
+            "POST" => self.post.as_ref(),
+            "PUT" => self.put.as_ref(),
+            "PATCH" => self.patch.as_ref(),
+            "DELETE" => self.delete.as_ref(),
+            _ => None,
+        }
+    }
+
+    // Every `auth` the route or one of its methods asks for
+    pub fn auth_schemes(&self) -> impl Iterator<Item = AuthScheme> + '_ {
+        let methods = [&self.get, &self.post, &self.put, &self.patch, &self.delete];
+        self.auth.into_iter().chain(
+            methods
+                .into_iter()
+                .filter_map(|method| method.as_ref()?.auth),
+        )
+    }
+
+    // Route-level settings with the method's own settings layered on top
+    pub fn response_for(&self, method: &str) -> MethodConfig {
+        let mut response = MethodConfig {
+            latency: self.latency,
+            bandwidth: self.bandwidth,
+            status: self.status,
+            headers: self.headers.clone(),
+            content_type: self.content_type.clone(),
+            body: self.body.clone(),
+            dynamic: self.dynamic,
+            chaos: self.chaos.clone(),
+            rate_limit: self.rate_limit.clone(),
+            auth: self.auth,
+            roles: self.roles.clone(),
+        };
+        if let Some(overrides) = self.method(method) {
+            response.overlay(overrides);
+        }
+        response
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Parent,
+    Pattern,
+    Exact,
+}
+
+fn normalize(route: &str) -> &str {
+    route.trim_matches('/')
+}
+
+// Literal segments make a pattern more specific than placeholders
+fn specificity(pattern: &str) -> usize {
+    pattern
+        .split('/')
+        .filter(|segment| *segment != "*" && !segment.starts_with(':'))
+        .count()
+}
+
+// Match `pattern` against `route`, collecting `:name` segments
+fn match_pattern(pattern: &str, route: &str) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let mut route_parts = route.split('/');
+
+    for segment in pattern.split('/') {
+        if segment == "*" {
+            return Some(params);
+        }
+        let part = route_parts.next()?;
+        if let Some(name) = segment.strip_prefix(':') {
+            params.insert(name.to_string(), part.to_string());
+        } else if segment != part {
+            return None;
+        }
+    }
+
+    route_parts.next().is_none().then_some(params)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct RouteTable {
+    routes: HashMap<String, RouteConfig>,
+}
+
+impl RouteTable {
+    pub fn new(routes: HashMap<String, RouteConfig>) -> Self {
+        RouteTable {
+            routes: routes
+                .into_iter()
+                .map(|(key, config)| (normalize(&key).to_string(), config))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    pub fn auth_schemes(&self) -> impl Iterator<Item = AuthScheme> + '_ {
+        self.routes.values().flat_map(RouteConfig::auth_schemes)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    // Whether `route` has an entry of its own, as opposed to matching a pattern
+    pub fn contains(&self, route: &str) -> bool {
+        self.routes.contains_key(normalize(route))
+    }
+
+    // Entries in `overrides` are layered over entries with the same key
+    pub fn merged(&self, overrides: &RouteTable) -> RouteTable {
+        let mut routes = self.routes.clone();
+        for (key, config) in &overrides.routes {
+            routes.entry(key.clone()).or_default().overlay(config);
+        }
+        RouteTable { routes }
+    }
+
+    /// Find the most specific config for a request path: an exact key wins over a
+    /// `:param`/`*` pattern, which wins over the parent collection of a record path.
+    pub fn find(&self, route: &str) -> Option<(&RouteConfig, HashMap<String, String>)> {
+        self.find_entry(route)
+            .map(|(_, config, params)| (config, params))
+    }
+
+    // Like `find`, but also returns the key of the matching entry
+    pub fn find_entry(&self, route: &str) -> Option<(&str, &RouteConfig, HashMap<String, String>)> {
+        let route = normalize(route);
+        let parent = route.rsplit_once('/').map(|(parent, _)| parent);
+
+        self.routes
+            .iter()
+            .filter_map(|(key, config)| {
+                let (kind, params) = if key == route {
+                    (MatchKind::Exact, HashMap::new())
+                } else if let Some(params) = match_pattern(key, route) {
+                    (MatchKind::Pattern, params)
+                } else if Some(key.as_str()) == parent {
+                    (MatchKind::Parent, HashMap::new())
+                } else {
+                    return None;
+                };
+                Some(((kind, specificity(key)), key, config, params))
+            })
+            .max_by_key(|(rank, _, _, _)| *rank)
+            .map(|(_, key, config, params)| (key.as_str(), config, params))
+    }
+
+    pub fn get(&self, route: &str) -> Option<&RouteConfig> {
+        self.find(route).map(|(config, _)| config)
+    }
+}
+
+/// Pull route definitions out of a loaded data file so `$routes` isn't served
+/// as a collection of its own.
+pub fn take_route_definitions(data: &mut Value) -> Result<RouteTable, String> {
+    let Some(definitions) = data.as_object_mut().and_then(|obj| obj.remove(ROUTES_KEY)) else {
+        return Ok(RouteTable::default());
+    };
+    serde_json::from_value::<HashMap<String, RouteConfig>>(definitions)
+        .map(RouteTable::new)
+        .map_err(|e| format!("Invalid `{}` in data file: {}", ROUTES_KEY, e))
+}
+
+// Route table that can be swapped when the data file is reloaded
+pub type SharedRoutes = Arc<std::sync::RwLock<Arc<RouteTable>>>;
+
+pub fn shared_routes(routes: RouteTable) -> SharedRoutes {
+    Arc::new(std::sync::RwLock::new(Arc::new(routes)))
+}
+
+pub fn current_routes(routes: &SharedRoutes) -> Arc<RouteTable> {
+    routes
+        .read()
+        .map(|routes| routes.clone())
+        .unwrap_or_default()
+}
+
+fn apply_headers(response: &mut Response, headers: &HashMap<String, String>) {
+    for (name, value) in headers {
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                response.headers_mut().insert(name, value);
+            }
+            _ => warn!("Skipping invalid header override `{}: {}`", name, value),
+        }
+    }
+}
+
+// Build the response for a route with a configured body, bypassing the data store
+fn static_response(config: &MethodConfig, body: &Value) -> Response {
+    let status = config
+        .status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+
+    // Strings are sent verbatim, anything else as JSON
+    let (default_type, bytes) = match body {
+        Value::String(text) => ("text/plain; charset=utf-8", text.clone().into_bytes()),
+        other => ("application/json", other.to_string().into_bytes()),
+    };
+    let content_type = config.content_type.as_deref().unwrap_or(default_type);
+
+    let mut response = (status, Body::from(bytes)).into_response();
+    if let Ok(value) = HeaderValue::from_str(content_type) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    apply_headers(&mut response, &config.headers);
+    response
+}
+
+/// Apply route definitions: routes with a `body` are answered directly, others
+/// get their status, content type and headers overridden on success.
+pub async fn route_overrides(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let routes = state.routes();
+    if routes.is_empty() {
+        return next.run(request).await;
+    }
+
+    let route = request.uri().path().trim_start_matches('/').to_string();
+    let method = request.method().clone();
+    let Some((config, path_params)) = routes.find(&route) else {
+        return next.run(request).await;
+    };
+    let mut overrides = config.response_for(method.as_str());
+
+    let request = if overrides.is_dynamic() {
+        // Buffer the body so templates can read it and the handler still gets it
+        let (parts, body) = request.into_parts();
+        let bytes = match to_bytes(body, MAX_TEMPLATE_BODY_BYTES).await {
+            Ok(bytes) => bytes,
+            Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        };
+        let context = RequestContext::new(
+            method.as_str(),
+            path_params,
+            parts.uri.query(),
+            &parts.headers,
+            &bytes,
+        );
+        overrides.body = overrides.body.map(|body| context.render(&body));
+        overrides.headers = overrides
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), context.render_text(value)))
+            .collect();
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    if let Some(body) = &overrides.body {
+        let start_time = Instant::now();
+        let date_time = Local::now().format("%Y/%m/%d - %H:%M:%S").to_string();
+
+        state.latency_for(&route, method.as_str()).wait().await;
+
+        let response = static_response(&overrides, body);
+        if !state.logs_disabled {
+            info!(
+                date_time = date_time,
+                status = response.status().as_str(),
+                method = method.as_str(),
+                path = request.uri().path(),
+                elapsed_ms = start_time.elapsed().as_millis(),
+                records = 0,
+                "HTTP request"
+            );
+        }
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+
+    if response.status().is_success() {
+        if let Some(status) = overrides
+            .status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+        {
+            *response.status_mut() = status;
+        }
+        if let Some(content_type) = overrides
+            .content_type
+            .as_deref()
+            .and_then(|ct| HeaderValue::from_str(ct).ok())
+        {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, content_type);
+        }
+    }
+    apply_headers(&mut response, &overrides.headers);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table(routes: Value) -> RouteTable {
+        RouteTable::new(serde_json::from_value(routes).unwrap())
+    }
+
+    #[test]
+    fn matches_placeholders_and_wildcards() {
+        let params = match_pattern("users/:id", "users/7").unwrap();
+        assert_eq!(params["id"], "7");
+        assert!(match_pattern("users/:id", "users/7/posts").is_none());
+        assert!(match_pattern("api/*", "api/v1/users").is_some());
+        assert!(match_pattern("api/*", "other").is_none());
+    }
+
+    #[test]
+    fn finds_the_most_specific_entry() {
+        let routes = table(json!({
+            "/users/": {"status": 200},
+            "users/:id": {"status": 201},
+            "users/me": {"status": 202},
+            "api/*": {"status": 203},
+        }));
+        let status = |route| routes.get(route).and_then(|config| config.status);
+
+        assert_eq!(status("users"), Some(200));
+        assert_eq!(status("users/7"), Some(201));
+        assert_eq!(status("users/me"), Some(202));
+        assert_eq!(status("api/v1/anything"), Some(203));
+        assert_eq!(status("posts"), None);
+        assert!(routes.contains("/users"));
+    }
+
+    #[test]
+    fn record_paths_fall_back_to_their_collection() {
+        let routes = table(json!({"posts": {"status": 418}}));
+        let (key, config, _) = routes.find_entry("posts/3").unwrap();
+        assert_eq!(key, "posts");
+        assert_eq!(config.status, Some(418));
+    }
+
+    #[test]
+    fn method_settings_layer_over_the_route() {
+        let routes = table(json!({"users": {
+            "status": 200,
+            "headers": {"x-a": "route", "x-b": "route"},
+            "post": {"status": 201, "headers": {"x-b": "post"}},
+        }}));
+        let config = routes.get("users").unwrap();
+
+        let post = config.response_for("post");
+        assert_eq!(post.status, Some(201));
+        assert_eq!(post.headers["x-a"], "route");
+        assert_eq!(post.headers["x-b"], "post");
+        assert_eq!(config.response_for("GET").status, Some(200));
+    }
+
+    #[test]
+    fn overrides_win_when_tables_merge() {
+        let file = table(json!({"users": {"status": 200, "page": 5}}));
+        let config = table(json!({"users": {"status": 202}, "posts": {"page": 2}}));
+        let merged = file.merged(&config);
+
+        let users = merged.get("users").unwrap();
+        assert_eq!((users.status, users.page), (Some(202), Some(5)));
+        assert_eq!(merged.get("posts").unwrap().page, Some(2));
+    }
+
+    #[test]
+    fn route_definitions_leave_the_data() {
+        let mut data = json!({"$routes": {"users": {"status": 201}}, "users": []});
+        let routes = take_route_definitions(&mut data).unwrap();
+        assert_eq!(data, json!({"users": []}));
+        assert_eq!(routes.get("users").unwrap().status, Some(201));
+
+        let mut data = json!({"$routes": {"users": {"colour": "red"}}});
+        assert!(take_route_definitions(&mut data).is_err());
+    }
+
+    #[test]
+    fn static_bodies_pick_a_content_type() {
+        let config = MethodConfig {
+            status: Some(202),
+            ..MethodConfig::default()
+        };
+        let response = static_response(&config, &json!("pong"));
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+
+        let response = static_response(&MethodConfig::default(), &json!({"ok": true}));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
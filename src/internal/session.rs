@@ -0,0 +1,279 @@
+use crate::internal::chimera::AppState;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderMap, HeaderValue, Method, Uri},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+pub const SESSION_HEADER: &str = "x-chimera-session";
+// `/__chimera/sessions/<id>/users` is `/users` in session `<id>`
+pub const SESSION_PREFIX: &str = "/__chimera/sessions";
+const DEFAULT_SESSION_TTL_SECS: u64 = 30 * 60;
+// Expired sessions are looked for at most this often
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `[sessions]` settings: how long a session may sit idle before it is dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    // Seconds; 0 keeps sessions until they are deleted
+    pub ttl: Option<u64>,
+}
+
+struct Session {
+    // `None` until the session's first write; reads share the pristine data till then
+    data: Option<Arc<RwLock<Value>>>,
+    created_at: DateTime<Utc>,
+    last_used: Instant,
+    requests: u64,
+}
+
+/// Isolated datasets keyed by session id. Each is a copy of the data the server
+/// loaded, whatever has happened to the shared dataset since, taken when the
+/// session first writes.
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+    ttl: Option<Duration>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl SessionStore {
+    pub fn new(config: &SessionConfig) -> Self {
+        let ttl = config.ttl.unwrap_or(DEFAULT_SESSION_TTL_SECS);
+        SessionStore {
+            sessions: Mutex::new(HashMap::new()),
+            ttl: (ttl > 0).then(|| Duration::from_secs(ttl)),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn expired(&self, session: &Session) -> bool {
+        self.ttl
+            .is_some_and(|ttl| session.last_used.elapsed() >= ttl)
+    }
+
+    // Drop every expired session now and then, and `id` if it has expired
+    fn sweep(&self, sessions: &mut HashMap<String, Session>, id: &str) {
+        if sessions
+            .get(id)
+            .is_some_and(|session| self.expired(session))
+        {
+            sessions.remove(id);
+        }
+        let mut last_sweep = self.last_sweep.lock().unwrap_or_else(|e| e.into_inner());
+        if last_sweep.elapsed() < SWEEP_INTERVAL {
+            return;
+        }
+        *last_sweep = Instant::now();
+        sessions.retain(|_, session| !self.expired(session));
+    }
+
+    /// The dataset session `id` should use, creating the session if it is new.
+    pub async fn dataset(
+        &self,
+        id: &str,
+        pristine: &Arc<RwLock<Value>>,
+        writes: bool,
+    ) -> Arc<RwLock<Value>> {
+        {
+            let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            self.sweep(&mut sessions, id);
+            let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+                data: None,
+                created_at: Utc::now(),
+                last_used: Instant::now(),
+                requests: 0,
+            });
+            session.last_used = Instant::now();
+            session.requests += 1;
+            match &session.data {
+                Some(data) => return data.clone(),
+                None if !writes => return pristine.clone(),
+                None => {}
+            }
+        }
+
+        // Copy outside the lock; a concurrent first write may have won meanwhile
+        let copy = Arc::new(RwLock::new(pristine.read().await.clone()));
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+            data: None,
+            created_at: Utc::now(),
+            last_used: Instant::now(),
+            requests: 1,
+        });
+        session.data.get_or_insert(copy).clone()
+    }
+
+    pub fn list(&self) -> Vec<Value> {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let mut listing: Vec<Value> = sessions
+            .iter()
+            .filter(|(_, session)| !self.expired(session))
+            .map(|(id, session)| {
+                json!({
+                    "id": id,
+                    "created_at": session.created_at.to_rfc3339(),
+                    "idle_seconds": session.last_used.elapsed().as_secs(),
+                    "requests": session.requests,
+                    "copied": session.data.is_some(),
+                })
+            })
+            .collect();
+        listing.sort_by(|a, b| a["created_at"].as_str().cmp(&b["created_at"].as_str()));
+        listing
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(id).is_some()
+    }
+
+    pub fn clear(&self) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+pub fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+}
+
+/// Turn `/__chimera/sessions/<id>/<path>` into `/<path>` with the session header
+/// set. Runs before routing, so the rewritten path is what gets matched.
+pub async fn session_prefix(mut request: Request, next: Next) -> Response {
+    let rewritten = request
+        .uri()
+        .path()
+        .strip_prefix(SESSION_PREFIX)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(id, path)| !id.is_empty() && !path.is_empty())
+        .and_then(|(id, path)| {
+            let path_and_query = match request.uri().query() {
+                Some(query) => format!("/{}?{}", path, query),
+                None => format!("/{}", path),
+            };
+            Some((
+                HeaderValue::from_str(id).ok()?,
+                path_and_query.parse::<Uri>().ok()?,
+            ))
+        });
+
+    if let Some((id, uri)) = rewritten {
+        request.headers_mut().insert(SESSION_HEADER, id);
+        *request.uri_mut() = uri;
+    }
+    next.run(request).await
+}
+
+/// The dataset a request reads and writes: its session's, or the shared one.
+pub struct Dataset {
+    pub json_value: Arc<RwLock<Value>>,
+    pub session: Option<String>,
+}
+
+impl Dataset {
+    // Sessions live in memory only, so only shared writes are persisted
+    pub fn mark_dirty(&self, state: &AppState) {
+        if self.session.is_none() {
+            state.mark_dirty();
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Dataset {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(id) = session_id(&parts.headers) else {
+            return Ok(Dataset {
+                json_value: state.json_value.clone(),
+                session: None,
+            });
+        };
+        // Reads share the pristine data, so a session only pays for a copy once it writes
+        let writes = parts.method != Method::GET && parts.method != Method::HEAD;
+        let pristine = state.snapshots.shared_pristine();
+        Ok(Dataset {
+            json_value: state.sessions.dataset(id, &pristine, writes).await,
+            session: Some(id.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sessions_share_pristine_until_they_write() {
+        let store = SessionStore::new(&SessionConfig::default());
+        let pristine = Arc::new(RwLock::new(json!({"users": [{"id": 1}]})));
+
+        // Reads don't copy anything
+        let read = store.dataset("a", &pristine, false).await;
+        assert!(Arc::ptr_eq(&read, &pristine));
+        assert_eq!(store.list()[0]["copied"], false);
+
+        let first = store.dataset("a", &pristine, true).await;
+        assert!(!Arc::ptr_eq(&first, &pristine));
+        first.write().await["users"] = json!([]);
+        assert_eq!(store.list()[0]["copied"], true);
+
+        let second = store.dataset("b", &pristine, true).await;
+        assert_eq!(*second.read().await, json!({"users": [{"id": 1}]}));
+        assert_eq!(*pristine.read().await, json!({"users": [{"id": 1}]}));
+        // Once copied, reads see the session's own changes
+        let again = store.dataset("a", &pristine, false).await;
+        assert_eq!(*again.read().await, json!({"users": []}));
+        assert_eq!(store.list().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn removed_sessions_start_over() {
+        let store = SessionStore::new(&SessionConfig { ttl: Some(0) });
+        let pristine = Arc::new(RwLock::new(json!({"n": 1})));
+        store.dataset("a", &pristine, true).await.write().await["n"] = json!(2);
+
+        assert!(store.remove("a"));
+        assert!(!store.remove("a"));
+        assert_eq!(
+            *store.dataset("a", &pristine, false).await.read().await,
+            json!({"n": 1})
+        );
+
+        store.clear();
+        assert!(store.list().is_empty());
+    }
+
+    #[test]
+    fn session_id_ignores_blank_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_id(&headers), None);
+        headers.insert(SESSION_HEADER, HeaderValue::from_static("  "));
+        assert_eq!(session_id(&headers), None);
+        headers.insert(SESSION_HEADER, HeaderValue::from_static(" worker-1 "));
+        assert_eq!(session_id(&headers), Some("worker-1"));
+    }
+}
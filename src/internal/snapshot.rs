@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::error;
+
+/// `[snapshots]` settings: a directory snapshots are kept in across restarts,
+/// and a snapshot to start from instead of the data file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub dir: Option<String>,
+    pub restore: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub data: Value,
+    pub saved_at: DateTime<Utc>,
+}
+
+// Names become file names, so keep them to a safe alphabet
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.')
+}
+
+fn snapshot_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+/// Read every `<name>.json` in `dir`; a missing directory has no snapshots yet.
+pub async fn load_dir(dir: &str) -> Result<BTreeMap<String, Snapshot>, String> {
+    let mut snapshots = BTreeMap::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+        Err(e) => return Err(format!("Failed to read snapshot dir {}: {}", dir, e)),
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .filter(|name| valid_name(name))
+        else {
+            continue;
+        };
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        let data = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid snapshot {}: {}", path.display(), e))?;
+        let saved_at = entry
+            .metadata()
+            .await
+            .and_then(|meta| meta.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        snapshots.insert(name.to_string(), Snapshot { data, saved_at });
+    }
+    Ok(snapshots)
+}
+
+/// Named copies of the dataset, plus the pristine data it was loaded with.
+pub struct SnapshotStore {
+    // Sessions read it until they first write. Never written to, only replaced
+    // when `--watch` reloads the data file
+    pristine: RwLock<Arc<tokio::sync::RwLock<Value>>>,
+    saved: Mutex<BTreeMap<String, Snapshot>>,
+    dir: Option<PathBuf>,
+}
+
+impl SnapshotStore {
+    pub fn new(pristine: Value, saved: BTreeMap<String, Snapshot>, dir: Option<&str>) -> Self {
+        SnapshotStore {
+            pristine: RwLock::new(Arc::new(tokio::sync::RwLock::new(pristine))),
+            saved: Mutex::new(saved),
+            dir: dir.map(PathBuf::from),
+        }
+    }
+
+    /// The pristine data itself, for sessions to share until they first write.
+    pub fn shared_pristine(&self) -> Arc<tokio::sync::RwLock<Value>> {
+        self.pristine
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub async fn pristine(&self) -> Value {
+        self.shared_pristine().read().await.clone()
+    }
+
+    pub fn set_pristine(&self, data: Value) {
+        *self.pristine.write().unwrap_or_else(|e| e.into_inner()) =
+            Arc::new(tokio::sync::RwLock::new(data));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        saved.get(name).map(|snapshot| snapshot.data.clone())
+    }
+
+    /// Name, save time and size of every snapshot.
+    pub fn list(&self) -> Vec<Value> {
+        let saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        saved
+            .iter()
+            .map(|(name, snapshot)| {
+                json!({
+                    "name": name,
+                    "saved_at": snapshot.saved_at.to_rfc3339(),
+                    "routes": snapshot.data.as_object().map_or(0, |routes| routes.len()),
+                })
+            })
+            .collect()
+    }
+
+    /// Keep `data` as `name`, replacing any snapshot of that name.
+    pub async fn save(&self, name: &str, data: Value) -> Result<(), String> {
+        if !valid_name(name) {
+            return Err(format!(
+                "Invalid snapshot name `{}`. Use letters, digits, `-`, `_` and `.`",
+                name
+            ));
+        }
+        if let Some(dir) = &self.dir {
+            let path = snapshot_file(dir, name);
+            let content = serde_json::to_vec_pretty(&data).map_err(|e| e.to_string())?;
+            let written = match tokio::fs::create_dir_all(dir).await {
+                Ok(()) => tokio::fs::write(&path, content).await,
+                Err(e) => Err(e),
+            };
+            written.map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        saved.insert(
+            name.to_string(),
+            Snapshot {
+                data,
+                saved_at: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> bool {
+        let removed = {
+            let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+            saved.remove(name).is_some()
+        };
+        if removed {
+            if let Some(dir) = &self.dir {
+                let path = snapshot_file(dir, name);
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    error!("Failed to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_safe_file_names() {
+        for name in ["seeded", "before-login_2", "v1.2"] {
+            assert!(valid_name(name), "{}", name);
+        }
+        for name in ["", ".hidden", "../etc", "a/b", "a b"] {
+            assert!(!valid_name(name), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_lists_and_deletes_in_memory() {
+        let store = SnapshotStore::new(json!({}), BTreeMap::new(), None);
+        store
+            .save("one", json!({"users": [], "posts": []}))
+            .await
+            .unwrap();
+        assert!(store.save("../one", json!({})).await.is_err());
+
+        assert_eq!(store.get("one"), Some(json!({"users": [], "posts": []})));
+        let listing = store.list();
+        assert_eq!(listing.len(), 1);
+        assert_eq!(listing[0]["routes"], 2);
+
+        assert!(store.delete("one").await);
+        assert!(!store.delete("one").await);
+        assert_eq!(store.get("one"), None);
+    }
+
+    #[tokio::test]
+    async fn snapshots_survive_a_restart_in_their_dir() {
+        let dir = std::env::temp_dir().join(format!("chimera-snapshots-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let store = SnapshotStore::new(json!({}), BTreeMap::new(), Some(dir));
+        store
+            .save("kept", json!({"users": [{"id": 1}]}))
+            .await
+            .unwrap();
+        store.save("gone", json!({})).await.unwrap();
+        assert!(store.delete("gone").await);
+
+        let loaded = load_dir(dir).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), ["kept"]);
+        assert_eq!(loaded["kept"].data, json!({"users": [{"id": 1}]}));
+
+        assert!(load_dir(dir).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::internal::json_data_generate::generate_value;
+use crate::internal::query::lookup;
+use axum::http::HeaderMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use regex::{Captures, Regex};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([^{}]+?)\s*\}\}").unwrap())
+}
+
+/// The parts of a request that `{{request.*}}` placeholders can refer to.
+#[derive(Debug, Default)]
+pub struct RequestContext {
+    pub method: String,
+    pub path: HashMap<String, String>,
+    pub query: Map<String, Value>,
+    pub headers: Map<String, Value>,
+    pub body: Value,
+}
+
+impl RequestContext {
+    pub fn new(
+        method: &str,
+        path: HashMap<String, String>,
+        query: Option<&str>,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Self {
+        // First value wins for repeated query params
+        let mut query_map = Map::new();
+        let pairs: Vec<(String, String)> =
+            serde_urlencoded::from_str(query.unwrap_or("")).unwrap_or_default();
+        for (key, value) in pairs {
+            query_map.entry(key).or_insert(Value::String(value));
+        }
+
+        let headers = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.as_str().to_string(), Value::String(value.to_string())))
+            })
+            .collect();
+
+        RequestContext {
+            method: method.to_string(),
+            path,
+            query: query_map,
+            headers,
+            body: parse_body(body),
+        }
+    }
+
+    fn resolve(&self, expr: &str) -> Option<Value> {
+        match expr {
+            "now" => return Some(Value::String(chrono::Utc::now().to_rfc3339())),
+            "uuid" => return Some(Value::String(uuid::Uuid::new_v4().to_string())),
+            "request.method" => return Some(Value::String(self.method.clone())),
+            _ => {}
+        }
+
+        if let Some(field_type) = expr.strip_prefix("faker.") {
+            let mut rng = StdRng::from_rng(&mut rand::rng());
+            return Some(generate_value(field_type, &mut rng, 0));
+        }
+
+        let (source, key) = expr.strip_prefix("request.")?.split_once('.')?;
+        let found = match source {
+            "path" => self.path.get(key).map(|v| Value::String(v.clone())),
+            "query" => self.query.get(key).cloned(),
+            "headers" => self.headers.get(&key.to_ascii_lowercase()).cloned(),
+            "body" => lookup(&self.body, key).cloned(),
+            _ => return None,
+        };
+        // Known sources render missing values as null / empty
+        Some(found.unwrap_or(Value::Null))
+    }
+
+    fn render_str(&self, template: &str) -> Value {
+        // A lone placeholder keeps the type of what it refers to (numbers, objects...)
+        if let Some(caps) = placeholder().captures(template) {
+            if caps[0].len() == template.len() {
+                if let Some(value) = self.resolve(&caps[1]) {
+                    return value;
+                }
+            }
+        }
+
+        let rendered = placeholder().replace_all(template, |caps: &Captures| {
+            match self.resolve(&caps[1]) {
+                Some(Value::String(s)) => s,
+                Some(Value::Null) => String::new(),
+                Some(other) => other.to_string(),
+                // Unknown helpers are left as written
+                None => caps[0].to_string(),
+            }
+        });
+        Value::String(rendered.into_owned())
+    }
+
+    /// Render every string inside `template`, keys included.
+    pub fn render(&self, template: &Value) -> Value {
+        match template {
+            Value::String(s) => self.render_str(s),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.render(v)).collect()),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (self.render_text(k), self.render(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    pub fn render_text(&self, template: &str) -> String {
+        match self.render_str(template) {
+            Value::String(s) => s,
+            Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+}
+
+// JSON bodies are used as-is, form bodies become an object, anything else a string
+fn parse_body(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(value) = serde_json::from_slice::<Value>(body) {
+        return value;
+    }
+    let text = String::from_utf8_lossy(body);
+    match serde_urlencoded::from_str::<Vec<(String, String)>>(&text) {
+        Ok(pairs) if !pairs.is_empty() && text.contains('=') => Value::Object(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect(),
+        ),
+        _ => Value::String(text.into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    fn context() -> RequestContext {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant", HeaderValue::from_static("acme"));
+        RequestContext::new(
+            "POST",
+            HashMap::from([("id".to_string(), "7".to_string())]),
+            Some("q=first&q=second&empty="),
+            &headers,
+            br#"{"user": {"name": "Ada", "age": 36}}"#,
+        )
+    }
+
+    #[test]
+    fn lone_placeholders_keep_their_type() {
+        let context = context();
+        assert_eq!(
+            context.render(&json!("{{request.body.user.age}}")),
+            json!(36)
+        );
+        assert_eq!(
+            context.render(&json!("{{ request.body.user }}")),
+            json!({"name": "Ada", "age": 36})
+        );
+        assert_eq!(
+            context.render(&json!("{{request.body.missing}}")),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn renders_placeholders_inside_text() {
+        let context = context();
+        let template = json!({
+            "{{request.path.id}}": "{{request.method}} by {{request.body.user.name}} for {{request.headers.X-Tenant}}",
+            "list": ["{{request.query.q}}", "{{request.query.none}}!", 3],
+            "unknown": "{{nope}} stays",
+        });
+        assert_eq!(
+            context.render(&template),
+            json!({
+                "7": "POST by Ada for acme",
+                "list": ["first", "!", 3],
+                "unknown": "{{nope}} stays",
+            })
+        );
+    }
+
+    #[test]
+    fn generated_values_are_fresh() {
+        let context = context();
+        let uuid = context.render_text("{{uuid}}");
+        assert_eq!(uuid.len(), 36);
+        assert_ne!(uuid, context.render_text("{{uuid}}"));
+        assert!(chrono::DateTime::parse_from_rfc3339(&context.render_text("{{now}}")).is_ok());
+    }
+
+    #[test]
+    fn parses_json_form_and_text_bodies() {
+        assert_eq!(parse_body(b""), Value::Null);
+        assert_eq!(parse_body(b"[1,2]"), json!([1, 2]));
+        assert_eq!(parse_body(b"a=1&b=two"), json!({"a": "1", "b": "two"}));
+        assert_eq!(parse_body(b"plain text"), json!("plain text"));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::internal::chimera::AppStateWs;
-use crate::internal::helpers::compare_values;
+use crate::internal::query::{sort_records, sort_spec, value_as_text, Projection};
+use crate::internal::rate_limit::Decision;
+use crate::internal::reload::ReloadEvent;
 use axum::body::Body;
 use axum::{
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
@@ -21,24 +23,50 @@ use uuid::Uuid;
 pub struct ConnectionState {
     id: String,
     ip: String,
-    route: String,
+    pub route: String,
     connected_at: chrono::DateTime<chrono::Utc>,
 }
 
+pub type WsSharedState = (
+    Arc<AppStateWs>,
+    Arc<RwLock<Value>>,
+    Arc<RwLock<HashMap<String, ConnectionState>>>,
+);
+
 #[derive(Deserialize)]
 pub struct WsCommand {
     action: String,
+    // Any other keys are treated as query params (e.g. `_sort`, `_order`)
+    #[serde(flatten)]
+    params: HashMap<String, Value>,
+}
+
+impl WsCommand {
+    fn query_params(&self) -> Vec<(String, String)> {
+        self.params
+            .iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => (k.clone(), s.clone()),
+                // `"_fields": ["id", "name"]` means the same as `"_fields": "id,name"`
+                Value::Array(items) => (
+                    k.clone(),
+                    items
+                        .iter()
+                        .map(value_as_text)
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                other => (k.clone(), other.to_string()),
+            })
+            .collect()
+    }
 }
 
 pub async fn handle_websocket(
     Path(route): Path<String>,
     ws: WebSocketUpgrade,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    State((state, json_data, connections)): State<(
-        Arc<AppStateWs>,
-        Arc<RwLock<Value>>,
-        Arc<RwLock<HashMap<String, ConnectionState>>>,
-    )>,
+    State((state, json_data, connections)): State<WsSharedState>,
 ) -> Response {
     let connection_id = Uuid::new_v4().to_string();
     let ip = addr.ip().to_string();
@@ -113,6 +141,7 @@ pub async fn handle_socket(
         &json_data,
         state.clone(),
         &route,
+        &[],
         &connection_id,
         &ip,
     )
@@ -127,12 +156,69 @@ pub async fn handle_socket(
         return;
     }
 
+    let mut reload_rx = state.reload_tx.subscribe();
+
     // Message handling loop
-    while let Some(msg_result) = socket.next().await {
+    loop {
+        let msg_result = tokio::select! {
+            msg = socket.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            event = reload_rx.recv() => {
+                if let Ok(event) = event {
+                    if let Err(e) = send_reload_notice(
+                        &mut socket,
+                        &json_data,
+                        state.clone(),
+                        &event,
+                        &route,
+                        &connection_id,
+                        &ip,
+                    )
+                    .await
+                    {
+                        error!(
+                            connection_id = %connection_id,
+                            error = %e,
+                            "Reload notification failed"
+                        );
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+
         match msg_result {
             Ok(msg) => {
                 match msg {
                     Message::Text(text) => {
+                        if let Some((scope, limit)) = state.rate_limit_for(&route) {
+                            let decision =
+                                state
+                                    .rate_limiter
+                                    .check(&ws_scope(&scope), &connection_id, &limit);
+                            if !decision.allowed {
+                                if let Err(e) = send_rate_limited(
+                                    &mut socket,
+                                    &decision,
+                                    &state,
+                                    &connection_id,
+                                    &ip,
+                                )
+                                .await
+                                {
+                                    error!(
+                                        connection_id = %connection_id,
+                                        error = %e,
+                                        "Rate limit notification failed"
+                                    );
+                                    break;
+                                }
+                                continue;
+                            }
+                        }
                         if let Err(e) = handle_text_message(
                             &mut socket,
                             text,
@@ -188,11 +274,13 @@ pub async fn handle_socket(
 }
 
 // Helper: Send route data
+#[allow(clippy::too_many_arguments)]
 pub async fn send_route_data(
     socket: &mut WebSocket,
     json_data: &Arc<RwLock<Value>>,
     state: Arc<AppStateWs>,
     route: &str,
+    params: &[(String, String)],
     connection_id: &str,
     ip: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -201,25 +289,28 @@ pub async fn send_route_data(
         Some(route_data) => {
             let mut value = route_data.clone();
 
-            // Apply sorting if specified for this route
-            if let Some((order, key)) = state.sort_rules.get(&route.to_string()) {
+            // Apply sorting from the command, falling back to the route's --sort rule
+            let sort_rule = state.sort_rule_for(route);
+            if let Some(spec) = sort_spec(params, sort_rule.as_ref(), state.nulls) {
                 if let Value::Array(arr) = &mut value {
-                    if arr.len() > 1 {
-                        use rayon::slice::ParallelSliceMut;
-                        arr.par_sort_by(|a, b| compare_values(a, b, key, order));
-                    }
+                    sort_records(arr, &spec);
                 }
             }
 
             // Apply pagination if enabled
-            if state.paginate > 0 {
+            let page_size = state.page_size_for(route);
+            if page_size > 0 {
                 if let Value::Array(arr) = &value {
-                    if arr.len() > state.paginate as usize {
-                        value = Value::Array(arr[..state.paginate as usize].to_vec());
+                    if arr.len() > page_size as usize {
+                        value = Value::Array(arr[..page_size as usize].to_vec());
                     }
                 }
             }
 
+            if let Some(projection) = Projection::from_params(params) {
+                projection.apply(&mut value);
+            }
+
             json!({
                 "status": "success",
                 "route": route,
@@ -251,6 +342,68 @@ pub async fn send_route_data(
         .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
 }
 
+// Helper: Tell the client the data file was reloaded, then push fresh route data
+pub async fn send_reload_notice(
+    socket: &mut WebSocket,
+    json_data: &Arc<RwLock<Value>>,
+    state: Arc<AppStateWs>,
+    event: &ReloadEvent,
+    route: &str,
+    connection_id: &str,
+    ip: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let notice = json!({
+        "status": "reload",
+        "route": route,
+        "message": format!("Data reloaded from {}", event.path)
+    });
+
+    if !state.logs_disabled {
+        info!(
+            timestamp = %Utc::now().format("%Y-%m-%d %H:%M:%S"),
+            connection_id = %connection_id,
+            action = "RELOAD",
+            ip = %ip,
+            "Sending reload notification"
+        );
+    }
+
+    socket.send(Message::Text(notice.to_string())).await?;
+    send_route_data(socket, json_data, state, route, &[], connection_id, ip).await
+}
+
+// Helper: Reject a message that went over the connection's rate limit
+pub async fn send_rate_limited(
+    socket: &mut WebSocket,
+    decision: &Decision,
+    state: &AppStateWs,
+    connection_id: &str,
+    ip: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let notice = json!({
+        "status": 429,
+        "error": "Too Many Requests",
+        "message": "Rate limit exceeded",
+        "limit": decision.limit,
+        "remaining": decision.remaining,
+        "retry_after": decision.retry_after
+    });
+
+    if !state.logs_disabled {
+        info!(
+            timestamp = %Utc::now().format("%Y-%m-%d %H:%M:%S"),
+            connection_id = %connection_id,
+            action = "RATE_LIMIT",
+            ip = %ip,
+            retry_after = decision.retry_after,
+            "Rate limit exceeded"
+        );
+    }
+
+    socket.send(Message::Text(notice.to_string())).await?;
+    Ok(())
+}
+
 // Helper: Send route command
 pub async fn send_route_command(
     socket: &mut WebSocket,
@@ -278,7 +431,47 @@ pub async fn send_route_command(
         .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
 }
 
+// Helper: Save, restore or list snapshots. Restoring pushes the new data to every
+// connection, like a reload of the data file
+pub async fn snapshot_command(
+    action: &str,
+    name: Option<&str>,
+    json_data: &Arc<RwLock<Value>>,
+    state: &AppStateWs,
+) -> Value {
+    let error = |message: String| json!({ "status": "error", "message": message });
+
+    let (data, source) = match (action, name) {
+        ("snapshots", _) => {
+            return json!({ "status": "success", "snapshots": state.snapshots.list() });
+        }
+        ("snapshot", Some(name)) => {
+            let data = json_data.read().await.clone();
+            return match state.snapshots.save(name, data).await {
+                Ok(()) => json!({ "status": "success", "snapshot": name }),
+                Err(e) => error(e),
+            };
+        }
+        ("restore", Some(name)) => match state.snapshots.get(name) {
+            Some(data) => (data, format!("snapshot {}", name)),
+            None => return error(format!("No snapshot named `{}`", name)),
+        },
+        ("reset", _) => (
+            state.snapshots.pristine().await,
+            "the initial data".to_string(),
+        ),
+        _ => return error(format!("`{}` needs a snapshot `name`", action)),
+    };
+
+    *json_data.write().await = data;
+    let _ = state.reload_tx.send(ReloadEvent {
+        path: source.clone(),
+    });
+    json!({ "status": "success", "restored": source })
+}
+
 // Helper: Handle text messages
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_text_message(
     socket: &mut WebSocket,
     text: String,
@@ -303,7 +496,9 @@ pub async fn handle_text_message(
                         "Received command"
                     );
                 }
-                send_route_data(socket, json_data, state, route, connection_id, ip).await?;
+                let params = cmd.query_params();
+                send_route_data(socket, json_data, state, route, &params, connection_id, ip)
+                    .await?;
             }
             "connections" => {
                 if !state.logs_disabled {
@@ -322,9 +517,9 @@ pub async fn handle_text_message(
                 // Create the response Value
                 let response_value = json!({
                     "status": "success",
-                    "connections": connections_data.iter().map(|(id, conn)| {
+                    "connections": connections_data.values().map(|conn| {
                         json!({
-                            "id": id,
+                            "id": conn.id,
                             "ip": conn.ip,
                             "route": conn.route,
                             "connected_at": conn.connected_at.to_rfc3339(),
@@ -338,6 +533,21 @@ pub async fn handle_text_message(
 
                 send_route_command(socket, &wrapped_response, connection_id, ip, state).await?;
             }
+            "snapshot" | "restore" | "reset" | "snapshots" => {
+                if !state.logs_disabled {
+                    info!(
+                        timestamp = %Utc::now().format("%Y-%m-%d %H:%M:%S"),
+                        connection_id = %connection_id,
+                        action = "RECV",
+                        bytes = text.len(),
+                        command = %cmd.action,
+                        "Received command"
+                    );
+                }
+                let name = cmd.params.get("name").and_then(Value::as_str);
+                let response = snapshot_command(&cmd.action, name, json_data, &state).await;
+                socket.send(Message::Text(response.to_string())).await?;
+            }
             _ => {
                 if !state.logs_disabled {
                     info!(
@@ -379,6 +589,11 @@ pub fn log_close(connection_id: &str) {
     );
 }
 
+// Message buckets are kept apart from HTTP buckets of the same route
+fn ws_scope(scope: &str) -> String {
+    format!("ws {}", scope)
+}
+
 // Helper: Clean up connection
 pub async fn cleanup_connection(
     connections: &Arc<RwLock<HashMap<String, ConnectionState>>>,
@@ -386,6 +601,10 @@ pub async fn cleanup_connection(
     state: Arc<AppStateWs>,
 ) {
     if let Some(conn) = connections.write().await.remove(connection_id) {
+        // Buckets are per connection, so nothing will use this one again
+        if let Some((scope, _)) = state.rate_limit_for(&conn.route) {
+            state.rate_limiter.forget(&ws_scope(&scope), connection_id);
+        }
         if !state.logs_disabled {
             info!(
                 timestamp = %Utc::now().format("%Y-%m-%d %H:%M:%S"),
@@ -406,4 +625,4 @@ pub async fn ws_fallback_handler() -> Response {
             "WebSocket endpoint requires a route: /ws/{route}",
         ))
         .unwrap()
-}
\ No newline at end of file
+}
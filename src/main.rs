@@ -1,115 +1,261 @@
+use crate::internal::admin::{admin_router, public_config};
+use crate::internal::auth::{login, refresh, require_auth, AuthScheme, LOGIN_PATH, REFRESH_PATH};
+use crate::internal::chaos::{chaos_faults, ChaosRng, Fault};
 use crate::internal::chimera::{AppState, AppStateWs, Config, CHIMERA_LATEST_VERSION};
-use crate::internal::helpers::{find_key_and_id_lengths, shutdown_signal};
+use crate::internal::config_file::FileConfig;
+use crate::internal::health::{health_router, Health};
+use crate::internal::helpers::{cors_layer, find_key_and_id_lengths, shutdown_signal};
 use crate::internal::http_handlers::{
-    delete_data, get_data, handle_form_submission, patch_data, ping_pong, post_data, put_data,
+    delete_data, get_data, handle_form_submission, mock_root, patch_data, post_data, put_data,
 };
-use crate::internal::json_data_generate::{generate_json_from_schema, JsonDataGeneratorSchema};
+use crate::internal::journal::{record_requests, Journal};
+use crate::internal::latency::{throttle_bandwidth, Latency};
+use crate::internal::loader::{file_extension, load_cors_origins, parse_data, CORS_FILE};
+use crate::internal::metrics::{http_metrics, track_metrics, ws_metrics, Metrics, METRICS_PATH};
+use crate::internal::persist::{sidecar_path, top_level_keys, JsonStyle, Persister};
 use crate::internal::port::find_available_port;
+use crate::internal::proxy::{
+    group_by_route, proxy_request, take_recordings, MatchOn, ProxyState, DEFAULT_MATCH_ON,
+    RECORDINGS_KEY,
+};
+use crate::internal::query::NullsOrder;
+use crate::internal::rate_limit::{rate_limit, RateLimitKey, RateLimiter};
+use crate::internal::records::DEFAULT_ID_FIELD;
+use crate::internal::reload::{DataWatcher, ReloadEvent, SharedOrigins, SharedReloadStatus};
+use crate::internal::routes::{
+    route_overrides, shared_routes, take_route_definitions, RouteTable, SharedRoutes, ROUTES_KEY,
+};
+use crate::internal::session::{session_prefix, SessionStore};
+use crate::internal::snapshot::{load_dir, SnapshotStore};
 use crate::internal::ws_handlers::{handle_websocket, ws_fallback_handler};
 use axum::{
-    http::Method,
-    routing::{delete, get, patch, post, put},
-    Router,
+    extract::Request,
+    http::{header, HeaderName},
+    middleware,
+    routing::{any, delete, get, patch, post, put},
+    Router, ServiceExt,
 };
-use clap::{Arg, Command};
-use csv::Reader;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
 use local_ip_address::local_ip;
-use serde_json::{Map, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Error as IOError;
 use std::net::SocketAddr;
 use std::path::Path as Std_path;
-use std::path::Path;
 use std::process;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tower_http::cors::{Any, CorsLayer};
+use tokio::sync::{broadcast, RwLock};
+use tower::Layer;
 use tracing::{debug, error, info, warn};
 use tracing_appender::rolling;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
 mod internal {
+    pub mod admin;
+    pub mod auth;
+    pub mod chaos;
     pub mod chimera;
+    pub mod config_file;
+    pub mod health;
     pub mod helpers;
     pub mod http_handlers;
+    pub mod journal;
     pub mod json_data_generate;
+    pub mod jwt;
+    pub mod latency;
+    pub mod loader;
+    pub mod metrics;
+    pub mod persist;
     pub mod port;
+    pub mod proxy;
+    pub mod query;
+    pub mod rate_limit;
+    pub mod records;
+    pub mod relations;
+    pub mod reload;
+    pub mod routes;
+    pub mod session;
+    pub mod snapshot;
+    pub mod template;
     pub mod ws_handlers;
 }
 
-async fn run_axum_server(config: Config) -> Result<(), IOError> {
+// Hot reload the data file (and chimera.cors) into the running server
+#[allow(clippy::too_many_arguments)]
+fn spawn_watcher(
+    config: &Config,
+    allowed_origins: &SharedOrigins,
+    routes: &SharedRoutes,
+    persister: Option<Arc<Persister>>,
+    snapshots: &Arc<SnapshotStore>,
+    sessions: Option<&Arc<SessionStore>>,
+    reload_tx: broadcast::Sender<ReloadEvent>,
+    last_reload: &SharedReloadStatus,
+) {
+    DataWatcher {
+        path: config.path.clone(),
+        auto_generate: config.auto_generate,
+        json_value: config.json_value.clone(),
+        allowed_origins: config.cors_file.is_some().then(|| allowed_origins.clone()),
+        routes: routes.clone(),
+        file_routes: config.file_routes.clone(),
+        persister,
+        snapshots: snapshots.clone(),
+        sessions: sessions.cloned(),
+        reload_tx,
+        last_reload: last_reload.clone(),
+        logs_disabled: config.logs_disabled,
+    }
+    .spawn();
+}
+
+// Read by the probes; built before `config` is moved into the server state
+fn health(config: &Config, routes: &SharedRoutes, last_reload: &SharedReloadStatus) -> Arc<Health> {
+    Arc::new(Health {
+        started_at: std::time::Instant::now(),
+        mode: config.mode.clone(),
+        source: config.path.clone(),
+        watch: config.watch,
+        json_value: config.json_value.clone(),
+        routes: routes.clone(),
+        last_reload: last_reload.clone(),
+    })
+}
+
+// The loaded data is the pristine state; `--restore` then swaps in a snapshot
+async fn open_snapshots(config: &mut Config) -> Arc<SnapshotStore> {
+    let snapshots = SnapshotStore::new(
+        config.json_value.read().await.clone(),
+        std::mem::take(&mut config.saved_snapshots),
+        config.snapshots.dir.as_deref(),
+    );
+    if let Some(name) = &config.snapshots.restore {
+        if let Some(data) = snapshots.get(name) {
+            *config.json_value.write().await = data;
+            info!("Restored snapshot {}", name);
+        }
+    }
+    Arc::new(snapshots)
+}
+
+async fn run_axum_server(mut config: Config) -> Result<(), IOError> {
+    let snapshots = open_snapshots(&mut config).await;
+    let persister = config.persist_to.as_ref().map(|target| {
+        let persister = Persister::spawn(
+            config.json_value.clone(),
+            target.into(),
+            config.persist_style.clone(),
+            config.logs_disabled,
+        );
+        persister.remember_order(config.key_order.clone());
+        persister.preserve(ROUTES_KEY, config.route_definitions.clone());
+        if !config.recordings.is_empty() {
+            persister.preserve(RECORDINGS_KEY, Some(group_by_route(&config.recordings)));
+        }
+        // A separate target starts out as a copy of what is being served
+        if persister.target() != Std_path::new(&config.path) {
+            persister.schedule();
+        }
+        persister
+    });
+
+    let allowed_origins: SharedOrigins =
+        Arc::new(std::sync::RwLock::new(config.allowed_origins.clone()));
+    let routes = shared_routes(config.routes.clone());
+    let (reload_tx, _) = broadcast::channel(16);
+    let last_reload = SharedReloadStatus::default();
+    let sessions = Arc::new(SessionStore::new(&config.sessions));
+    if config.watch {
+        spawn_watcher(
+            &config,
+            &allowed_origins,
+            &routes,
+            persister.clone(),
+            &snapshots,
+            Some(&sessions),
+            reload_tx,
+            &last_reload,
+        );
+    }
+    let health = health(&config, &routes, &last_reload);
+
+    let startup_config = public_config(&config);
     let state = Arc::new(AppState {
         json_value: config.json_value,
-        latency: config.latency,
+        snapshots,
+        sessions,
+        config: startup_config,
+        latency: std::sync::RwLock::new(config.latency),
+        bandwidth: config.bandwidth,
         sort_rules: config.sort_rules,
+        nulls: config.nulls,
         paginate: config.paginate,
+        envelope: config.envelope,
+        id_field: config.id_field,
+        single_as_array: config.single_as_array,
+        chaos_rng: ChaosRng::new(config.chaos.seed),
+        chaos: std::sync::RwLock::new(config.chaos),
+        rate_limit: config.rate_limit,
+        rate_limiter: RateLimiter::default(),
+        auth: config.auth,
+        journal: Journal::spawn(&config.journal),
+        metrics: Metrics::default(),
         logs_disabled: config.logs_disabled,
+        persister,
+        routes,
     });
 
     info!("Running HTTP");
 
-    let cors_layer = if config.cors_enabled {
-        let allowed_origins = config
-            .allowed_origins
-            .iter()
-            .filter_map(|origin| origin.parse::<axum::http::HeaderValue>().ok())
-            .collect::<Vec<_>>();
-
-        if config.allowed_origins.is_empty() {
-            info!("CORS: *");
-            CorsLayer::new()
-                .allow_methods([
-                    Method::GET,
-                    Method::POST,
-                    Method::PUT,
-                    Method::PATCH,
-                    Method::DELETE,
-                    Method::OPTIONS,
-                ])
-                .allow_headers(Any)
-                .allow_origin(Any)
-                .allow_credentials(false)
-        } else {
-            info!("CORS: chimera.cors");
-            CorsLayer::new()
-                .allow_methods([
-                    Method::GET,
-                    Method::POST,
-                    Method::PUT,
-                    Method::PATCH,
-                    Method::DELETE,
-                    Method::OPTIONS,
-                ])
-                .allow_headers(Any)
-                .allow_origin(allowed_origins)
-                .allow_credentials(false)
-        }
-    } else {
-        info!("CORS: *");
-        CorsLayer::new()
-            .allow_methods([
-                Method::GET,
-                Method::POST,
-                Method::PUT,
-                Method::PATCH,
-                Method::DELETE,
-            ])
-            .allow_headers(Any)
-            .allow_origin(Any)
-    };
+    let cors_layer = cors_layer(config.cors_enabled, allowed_origins.clone());
+    // Let browser clients read the pagination and rate limit headers
+    let cors_layer = cors_layer.expose_headers([
+        HeaderName::from_static("x-total-count"),
+        header::LINK,
+        HeaderName::from_static("x-ratelimit-limit"),
+        HeaderName::from_static("x-ratelimit-remaining"),
+        HeaderName::from_static("x-ratelimit-reset"),
+        header::RETRY_AFTER,
+        header::WWW_AUTHENTICATE,
+    ]);
 
     // Build router with Axum
     let app = Router::new()
-        .route("/", get(ping_pong))
+        // Nothing of its own, so a `$routes` entry for `/` can mock it
+        .route("/", any(mock_root))
         .route("/submit-form", post(handle_form_submission))
+        .route(LOGIN_PATH, post(login))
+        .route(REFRESH_PATH, post(refresh))
         .route("/*route", get(get_data))
         .route("/*route", delete(delete_data))
         .route("/*route", post(post_data))
         .route("/*route", put(put_data))
         .route("/*route", patch(patch_data))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            route_overrides,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), chaos_faults))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            throttle_bandwidth,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), require_auth))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), track_metrics))
+        // Merged after the layers above, so control requests aren't delayed, faulted or guarded
+        .merge(admin_router())
+        .merge(health_router(health))
+        .route(METRICS_PATH, get(http_metrics))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            record_requests,
+        ))
         .layer(cors_layer)
         .with_state(state.clone());
+    // Wraps the router rather than its routes, so session paths are rewritten before routing
+    let app = middleware::from_fn(session_prefix).layer(app);
 
     // Address to bind the server
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
@@ -126,7 +272,7 @@ async fn run_axum_server(config: Config) -> Result<(), IOError> {
     // Wait for the server to complete (or for a shutdown signal)
     if let Err(e) = axum::serve(
         listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
+        ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
     )
     .with_graceful_shutdown(shutdown_signal())
     .await
@@ -136,6 +282,79 @@ async fn run_axum_server(config: Config) -> Result<(), IOError> {
         info!("Received shutdown signal, starting graceful shutdown");
     }
 
+    // Don't lose mutations still waiting on the debounce
+    if let Some(persister) = &state.persister {
+        persister.flush().await;
+    }
+
+    Ok(())
+}
+
+async fn run_proxy_server(config: Config) -> Result<(), IOError> {
+    let persister = Persister::spawn(
+        config.json_value.clone(),
+        config.path.clone().into(),
+        config.persist_style.clone(),
+        config.logs_disabled,
+    );
+    persister.remember_order(config.key_order.clone());
+    persister.preserve(ROUTES_KEY, config.route_definitions.clone());
+    if !config.recordings.is_empty() {
+        persister.preserve(RECORDINGS_KEY, Some(group_by_route(&config.recordings)));
+    }
+
+    let target = config.proxy.target.clone();
+    let state = Arc::new(ProxyState {
+        replay: config.proxy.replay.unwrap_or(false) || target.is_none(),
+        target,
+        match_on: config
+            .proxy
+            .match_on
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MATCH_ON.to_vec()),
+        client: ProxyState::client(),
+        recordings: std::sync::Mutex::new(config.recordings),
+        json_value: config.json_value.clone(),
+        persister: persister.clone(),
+        logs_disabled: config.logs_disabled,
+    });
+
+    match (&state.target, state.replay) {
+        (Some(target), false) => info!("Recording {} into {}", target, config.path),
+        (Some(target), true) => info!(
+            "Replaying {}, recording misses from {}",
+            config.path, target
+        ),
+        (None, _) => info!("Replaying {}", config.path),
+    }
+
+    let allowed_origins: SharedOrigins =
+        Arc::new(std::sync::RwLock::new(config.allowed_origins.clone()));
+    let cors_layer = cors_layer(config.cors_enabled, allowed_origins);
+    let app = Router::new()
+        .fallback(proxy_request)
+        .layer(cors_layer)
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    let local = "127.0.0.1";
+    let lan_ip = local_ip().unwrap_or_else(|_| local.parse().unwrap());
+    info!("Local: http://{}:{}", local, config.port);
+    info!("Network: http://{}:{}", lan_ip, config.port);
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+    {
+        error!("Server error: {}", e);
+    } else {
+        info!("Received shutdown signal, starting graceful shutdown");
+    }
+
+    // Write recordings still waiting on the debounce
+    persister.flush().await;
+
     Ok(())
 }
 
@@ -145,71 +364,50 @@ async fn run_grpc_server(config: Config) -> Result<(), IOError> {
     Ok(())
 }
 
-pub async fn run_websocket_server(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn run_websocket_server(mut config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots = open_snapshots(&mut config).await;
     let shared_data = config.json_value.clone();
+    let allowed_origins: SharedOrigins =
+        Arc::new(std::sync::RwLock::new(config.allowed_origins.clone()));
+    let routes = shared_routes(config.routes.clone());
+    let (reload_tx, _) = broadcast::channel(16);
+    let last_reload = SharedReloadStatus::default();
+    if config.watch {
+        spawn_watcher(
+            &config,
+            &allowed_origins,
+            &routes,
+            None,
+            &snapshots,
+            None,
+            reload_tx.clone(),
+            &last_reload,
+        );
+    }
+    let health = health(&config, &routes, &last_reload);
+
     let state = Arc::new(AppStateWs {
         sort_rules: config.sort_rules,
+        nulls: config.nulls,
         paginate: config.paginate,
         logs_disabled: config.logs_disabled,
+        reload_tx,
+        routes,
+        rate_limit: config.rate_limit,
+        rate_limiter: RateLimiter::default(),
+        snapshots,
     });
     let connections = Arc::new(RwLock::new(HashMap::new()));
 
     info!("Running Websocket");
 
-    // Create CORS layer with proper method conversion
-    let cors_layer = if config.cors_enabled {
-        let allowed_origins = config
-            .allowed_origins
-            .iter()
-            .filter_map(|origin| origin.parse::<axum::http::HeaderValue>().ok())
-            .collect::<Vec<_>>();
-
-        if config.allowed_origins.is_empty() {
-            info!("CORS: *");
-            CorsLayer::new()
-                .allow_methods([
-                    Method::GET,
-                    Method::POST,
-                    Method::PUT,
-                    Method::PATCH,
-                    Method::DELETE,
-                    Method::OPTIONS,
-                ])
-                .allow_headers(Any)
-                .allow_origin(Any)
-                .allow_credentials(false)
-        } else {
-            info!("CORS: chimera.cors");
-            CorsLayer::new()
-                .allow_methods([
-                    Method::GET,
-                    Method::POST,
-                    Method::PUT,
-                    Method::PATCH,
-                    Method::DELETE,
-                    Method::OPTIONS,
-                ])
-                .allow_headers(Any)
-                .allow_origin(allowed_origins)
-                .allow_credentials(false)
-        }
-    } else {
-        info!("CORS: *");
-        CorsLayer::new()
-            .allow_methods([
-                Method::GET,
-                Method::POST,
-                Method::PUT,
-                Method::PATCH,
-                Method::DELETE,
-            ])
-            .allow_headers(Any)
-            .allow_origin(Any)
-    };
+    let cors_layer = cors_layer(config.cors_enabled, allowed_origins.clone());
 
     let app = Router::new()
         .route("/ws/*route", get(handle_websocket))
         .route("/ws", get(ws_fallback_handler))
+        .route(METRICS_PATH, get(ws_metrics))
+        .merge(health_router(health))
         .with_state((state, shared_data, connections))
         .layer(cors_layer);
 
@@ -233,20 +431,48 @@ pub async fn run_websocket_server(config: Config) -> Result<(), Box<dyn std::err
     Ok(())
 }
 
+// Value of a subcommand arg, only when it was typed on the command line
+fn cli_value(matches: Option<&ArgMatches>, id: &str) -> Option<String> {
+    let matches = matches?;
+    if matches.try_contains_id(id).is_err()
+        || matches.value_source(id) != Some(ValueSource::CommandLine)
+    {
+        return None;
+    }
+    matches.get_one::<String>(id).cloned()
+}
+
+fn cli_values(matches: Option<&ArgMatches>, id: &str) -> Option<Vec<String>> {
+    cli_value(matches, id)?;
+    Some(matches?.get_many::<String>(id)?.cloned().collect())
+}
+
+// A subcommand flag that was passed on the command line
+fn cli_flag(matches: Option<&ArgMatches>, id: &str) -> Option<bool> {
+    let matches = matches?;
+    if matches.try_contains_id(id).is_err() {
+        return None;
+    }
+    matches.get_flag(id).then_some(true)
+}
+
 async fn initialize_cmd() -> Result<Config, IOError> {
-    let matches = Command::new("Chimera - Mock SeRVeR")
+    let mut command = Command::new("Chimera - Mock SeRVeR")
         .version(CHIMERA_LATEST_VERSION)
         .author("Abhijith M S")
         .about("Multi-Protocol⚡ Mock SeRVeR built in Rust 🦀")
-        .arg_required_else_help(true)
 
         // Common Args
         .arg(Arg::new("path")
             .short('P')
             .long("path")
             .num_args(1)
-            .required(true)
             .help("Path to the Json file"))
+        .arg(Arg::new("config")
+            .short('C')
+            .long("config")
+            .num_args(1)
+            .help("Path to a chimera.toml / chimera.yaml config file (defaults to one in the current directory)"))
         .arg(Arg::new("quiet")
             .long("quiet")
             .num_args(0)
@@ -267,19 +493,100 @@ async fn initialize_cmd() -> Result<Config, IOError> {
                 .long("latency")
                 .num_args(1)
                 .default_value("0")
-                .help("Simulate latency (ms) from the server (Throttle)"))
+                .help("Simulate latency (ms) from the server: 100, 50..200ms, normal(100,20) or p50=40,p95=200,p99=800"))
+            .arg(Arg::new("bandwidth")
+                .long("bandwidth")
+                .num_args(1)
+                .help("Limit response bodies to this many bytes per second (e.g. 50000 for a slow 3G link)"))
             .arg(Arg::new("sort")
                 .short('S')
                 .long("sort")
                 .num_args(1..)
                 .action(clap::ArgAction::Append)
                 .help("Sort entries in each route (e.g., --sort <route> <asc|desc> <attribute_in_route>)"))
+            .arg(Arg::new("nulls")
+                .long("nulls")
+                .num_args(1)
+                .default_value("last")
+                .value_parser(["first", "last"])
+                .help("Where null or missing fields go when sorting"))
             .arg(Arg::new("page")
                 .short('A')
                 .long("page")
                 .num_args(1)
                 .default_value("0")
                 .help("Paginate records in the GET request"))
+            .arg(Arg::new("envelope")
+                .long("envelope")
+                .num_args(0)
+                .help("Wrap paginated GET responses as {data, meta}"))
+            .arg(Arg::new("id_field")
+                .long("id-field")
+                .num_args(1)
+                .default_value("id")
+                .help("Field that identifies a record in /{route}/{id} requests (e.g. _id, uuid)"))
+            .arg(Arg::new("single_as_array")
+                .long("single-as-array")
+                .num_args(0)
+                .help("Return /{route}/{id} as a one-element array (and [] when missing) like older releases"))
+            .arg(Arg::new("chaos")
+                .long("chaos")
+                .num_args(1)
+                .help("Inject faults into this percentage of requests (e.g. --chaos 10)"))
+            .arg(Arg::new("chaos_faults")
+                .long("chaos-faults")
+                .num_args(1)
+                .value_delimiter(',')
+                .help("Faults to pick from: 500,503,429,drop,truncate,malformed,slow [default: all]"))
+            .arg(Arg::new("chaos_seed")
+                .long("chaos-seed")
+                .num_args(1)
+                .help("Seed the fault picker so runs are reproducible"))
+            .arg(Arg::new("rate_limit")
+                .long("rate-limit")
+                .num_args(1)
+                .help("Allow this many requests per client every --rate-limit-window seconds, then answer 429"))
+            .arg(Arg::new("rate_limit_window")
+                .long("rate-limit-window")
+                .num_args(1)
+                .help("Window for --rate-limit in seconds [default: 60]"))
+            .arg(Arg::new("rate_limit_by")
+                .long("rate-limit-by")
+                .num_args(1)
+                .help("Who a limit applies to: ip, global, api-key (X-API-Key) or header:<name> [default: ip]"))
+            .arg(Arg::new("auth")
+                .long("auth")
+                .num_args(1)
+                .value_parser(["none", "basic", "api-key", "bearer"])
+                .help("Protect every route: basic (users collection), api-key (X-API-Key) or bearer (HS256 JWT)"))
+            .arg(Arg::new("jwt_secret")
+                .long("jwt-secret")
+                .num_args(1)
+                .help("Secret for signing and checking bearer tokens, required by --auth bearer and /auth/login"))
+            .arg(Arg::new("api_key")
+                .long("api-key")
+                .num_args(1)
+                .help("The API key accepted by --auth api-key"))
+            .arg(Arg::new("journal_size")
+                .long("journal-size")
+                .num_args(1)
+                .help("How many requests GET /__chimera/requests keeps [default: 1000]"))
+            .arg(Arg::new("journal_file")
+                .long("journal-file")
+                .num_args(1)
+                .help("Also append every request to this JSON Lines file"))
+            .arg(Arg::new("snapshot_dir")
+                .long("snapshot-dir")
+                .num_args(1)
+                .help("Keep named snapshots of the data in this directory across restarts"))
+            .arg(Arg::new("restore")
+                .long("restore")
+                .num_args(1)
+                .help("Start from this snapshot in --snapshot-dir instead of the data file"))
+            .arg(Arg::new("session_ttl")
+                .long("session-ttl")
+                .num_args(1)
+                .help("Drop X-Chimera-Session datasets idle for this many seconds, 0 to keep them [default: 1800]"))
             .arg(Arg::new("auto_generate_data")
                 .short('X')
                 .long("auto_generate_data")
@@ -289,6 +596,19 @@ async fn initialize_cmd() -> Result<Config, IOError> {
                 .long("cors")
                 .num_args(0)
                 .help("Enable CORS support (reads allowed domains from chimera.cors file)"))
+            .arg(Arg::new("watch")
+                .short('W')
+                .long("watch")
+                .num_args(0)
+                .help("Reload the data file and chimera.cors when they change"))
+            .arg(Arg::new("persist")
+                .long("persist")
+                .num_args(0)
+                .help("Write mutations back to the data file (generated data goes to <name>.data.json)"))
+            .arg(Arg::new("persist_to")
+                .long("persist-to")
+                .num_args(1)
+                .help("Write mutations to this file instead, leaving the data file untouched"))
         )
 
         // Args to `websocket`
@@ -307,6 +627,12 @@ async fn initialize_cmd() -> Result<Config, IOError> {
                     .num_args(1..)
                     .action(clap::ArgAction::Append)
                     .help("Sort entries in each route (e.g., --sort <route> <asc|desc> <attribute_in_route>)"))
+                .arg(Arg::new("nulls")
+                    .long("nulls")
+                    .num_args(1)
+                    .default_value("last")
+                    .value_parser(["first", "last"])
+                    .help("Where null or missing fields go when sorting"))
                 .arg(Arg::new("page")
                     .short('A')
                     .long("page")
@@ -317,196 +643,363 @@ async fn initialize_cmd() -> Result<Config, IOError> {
                     .long("cors")
                     .num_args(0)
                     .help("Enable CORS support (reads allowed domains from chimera.cors file)"))
+                .arg(Arg::new("watch")
+                    .short('W')
+                    .long("watch")
+                    .num_args(0)
+                    .help("Reload the data file and chimera.cors when they change"))
+                .arg(Arg::new("rate_limit")
+                    .long("rate-limit")
+                    .num_args(1)
+                    .help("Allow this many messages per connection every --rate-limit-window seconds, then reply with a 429 error"))
+                .arg(Arg::new("rate_limit_window")
+                    .long("rate-limit-window")
+                    .num_args(1)
+                    .help("Window for --rate-limit in seconds [default: 60]"))
+                .arg(Arg::new("snapshot_dir")
+                    .long("snapshot-dir")
+                    .num_args(1)
+                    .help("Keep named snapshots of the data in this directory across restarts"))
+                .arg(Arg::new("restore")
+                    .long("restore")
+                    .num_args(1)
+                    .help("Start from this snapshot in --snapshot-dir instead of the data file"))
                 .arg(Arg::new("auto_generate_data")
                     .short('X')
                     .long("auto_generate_data")
                     .num_args(0)
                     .help("Auto generate data without a .json sample file. A route schema .json file should be passed to --path"))
         )
-        .get_matches();
 
-    let json_file_path = matches
+        // Args to `proxy`
+        .subcommand(
+            Command::new("proxy")
+                .about("Forward requests to a real API, recording them into --path, or replay recordings offline")
+                .arg(Arg::new("port")
+                    .short('p')
+                    .long("port")
+                    .num_args(1)
+                    .default_value("8080")
+                    .help("Port for the proxy server"))
+                .arg(Arg::new("target")
+                    .short('t')
+                    .long("target")
+                    .num_args(1)
+                    .help("Base URL of the API to forward to and record (e.g. http://staging.example.com)"))
+                .arg(Arg::new("replay")
+                    .short('R')
+                    .long("replay")
+                    .num_args(0)
+                    .help("Answer from recordings; with --target, only unrecorded requests are forwarded"))
+                .arg(Arg::new("match")
+                    .short('m')
+                    .long("match")
+                    .num_args(1)
+                    .value_delimiter(',')
+                    .help("Request parts a recording must match: method,path,query,body [default: method,path,query]"))
+                .arg(Arg::new("cors")
+                    .long("cors")
+                    .num_args(0)
+                    .help("Enable CORS support (reads allowed domains from chimera.cors file)"))
+        );
+    let matches = command.get_matches_mut();
+
+    let config_arg = matches.get_one::<String>("config").map(String::as_str);
+    let file_config = match FileConfig::load(config_arg).await {
+        Ok(Some((file_config, _))) => file_config,
+        Ok(None) => FileConfig::default(),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let json_file_path = match matches
         .get_one::<String>("path")
-        .expect("Missing path argument")
-        .to_string();
-    let logs_disabled = matches.get_flag("quiet");
-
-    // Default values for subcommand-specific args
-    let mut server_port = 8080;
-    let mut sim_latency = 0;
-    let mut pagination_factor = 0;
-    let mut auto_generate_enabled = false;
-    let mut cors_enabled = false;
-    let mut sort_rules: HashMap<String, (String, String)> = HashMap::new();
-    let mut mode = "http";
-
-    if let Some(http_matches) = matches.subcommand_matches("http") {
-        server_port = http_matches
-            .get_one::<String>("port")
-            .unwrap()
-            .parse::<u16>()
-            .expect("Invalid port number");
-
-        let sim_latency_str = http_matches.get_one::<String>("latency").unwrap();
-        sim_latency = sim_latency_str
-            .trim_end_matches("ms")
-            .parse::<u64>()
-            .unwrap_or(0);
-
-        pagination_factor = http_matches
-            .get_one::<String>("page")
-            .unwrap()
-            .parse::<u64>()
-            .expect("Invalid page format");
-
-        auto_generate_enabled = http_matches.get_flag("auto_generate_data");
-        cors_enabled = http_matches.get_flag("cors");
-
-        if let Some(sort_args) = http_matches.get_many::<String>("sort") {
-            let sort_list: Vec<String> = sort_args.map(|s| s.clone()).collect();
-            for sort_group in sort_list.chunks(3) {
-                if let [route, order, key] = sort_group {
-                    sort_rules.insert(route.clone(), (order.clone(), key.clone()));
-                }
+        .cloned()
+        .or(file_config.path.clone())
+    {
+        Some(path) => path,
+        None => {
+            eprintln!("Missing data file: pass --path or set `path` in chimera.toml\n");
+            let _ = command.print_help();
+            process::exit(1);
+        }
+    };
+    let logs_disabled = matches.get_flag("quiet") || file_config.quiet.unwrap_or(false);
+
+    // Command line flags win over the config file, which wins over the defaults
+    let (mode, sub_matches) = match matches.subcommand() {
+        Some((name, sub_matches)) => (name.to_string(), Some(sub_matches)),
+        None => (
+            file_config
+                .mode
+                .clone()
+                .unwrap_or_else(|| "http".to_string()),
+            None,
+        ),
+    };
+
+    let server_port = cli_value(sub_matches, "port")
+        .map(|port| port.parse::<u16>().expect("Invalid port number"))
+        .or(file_config.port)
+        .unwrap_or(8080);
+    let sim_latency = match cli_value(sub_matches, "latency").map(|spec| Latency::parse(&spec)) {
+        Some(Ok(latency)) => latency,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        None => file_config.latency.unwrap_or_default(),
+    };
+    let bandwidth = cli_value(sub_matches, "bandwidth")
+        .map(|bandwidth| bandwidth.parse::<u64>().expect("Invalid bandwidth"))
+        .or(file_config.bandwidth)
+        .unwrap_or(0);
+    let pagination_factor = cli_value(sub_matches, "page")
+        .map(|page| page.parse::<u64>().expect("Invalid page format"))
+        .or(file_config.page)
+        .unwrap_or(0);
+    let envelope = cli_flag(sub_matches, "envelope")
+        .or(file_config.envelope)
+        .unwrap_or(false);
+    let id_field = cli_value(sub_matches, "id_field")
+        .or(file_config.id_field.clone())
+        .unwrap_or_else(|| DEFAULT_ID_FIELD.to_string());
+    let single_as_array = cli_flag(sub_matches, "single_as_array")
+        .or(file_config.single_as_array)
+        .unwrap_or(false);
+    let mut chaos = file_config.chaos.clone().unwrap_or_default();
+    if let Some(rate) = cli_value(sub_matches, "chaos") {
+        chaos.rate = Some(
+            rate.trim_end_matches('%')
+                .parse()
+                .expect("Invalid chaos rate"),
+        );
+    }
+    if let Some(names) = cli_values(sub_matches, "chaos_faults") {
+        let faults = names
+            .iter()
+            .map(|name| Fault::parse(name.trim()))
+            .collect::<Option<Vec<_>>>();
+        match faults {
+            Some(faults) => chaos.faults = Some(faults),
+            None => {
+                eprintln!(
+                    "Invalid --chaos-faults. Use any of 500,503,429,drop,truncate,malformed,slow"
+                );
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(seed) = cli_value(sub_matches, "chaos_seed") {
+        chaos.seed = Some(seed.parse().expect("Invalid chaos seed"));
+    }
+    let mut rate_limit = file_config.rate_limit.clone().unwrap_or_default();
+    if let Some(requests) = cli_value(sub_matches, "rate_limit") {
+        rate_limit.requests = Some(requests.parse().expect("Invalid rate limit"));
+    }
+    if let Some(window) = cli_value(sub_matches, "rate_limit_window") {
+        rate_limit.window = Some(
+            window
+                .trim_end_matches('s')
+                .parse()
+                .expect("Invalid rate limit window"),
+        );
+    }
+    if let Some(by) = cli_value(sub_matches, "rate_limit_by") {
+        match RateLimitKey::try_from(by) {
+            Ok(key) => rate_limit.by = Some(key),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
             }
         }
     }
+    let mut auth = file_config.auth.clone().unwrap_or_default();
+    if let Some(scheme) = cli_value(sub_matches, "auth") {
+        auth.scheme = AuthScheme::parse(&scheme);
+    }
+    if let Some(secret) = cli_value(sub_matches, "jwt_secret") {
+        auth.secret = Some(secret);
+    }
+    if let Some(key) = cli_value(sub_matches, "api_key") {
+        auth.api_key = Some(key);
+    }
+    let mut journal = file_config.journal.clone().unwrap_or_default();
+    if let Some(size) = cli_value(sub_matches, "journal_size") {
+        journal.size = Some(size.parse().expect("Invalid journal size"));
+    }
+    if let Some(file) = cli_value(sub_matches, "journal_file") {
+        journal.file = Some(file);
+    }
+    let mut snapshots = file_config.snapshots.clone().unwrap_or_default();
+    if let Some(dir) = cli_value(sub_matches, "snapshot_dir") {
+        snapshots.dir = Some(dir);
+    }
+    if let Some(name) = cli_value(sub_matches, "restore") {
+        snapshots.restore = Some(name);
+    }
+    let saved_snapshots = match snapshots.dir.as_deref() {
+        Some(dir) => match load_dir(dir).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        None => BTreeMap::new(),
+    };
+    if let Some(name) = &snapshots.restore {
+        if !saved_snapshots.contains_key(name) {
+            eprintln!(
+                "No snapshot named `{}` to restore. Save one first, and pass --snapshot-dir",
+                name
+            );
+            process::exit(1);
+        }
+    }
+    let mut sessions = file_config.sessions.clone().unwrap_or_default();
+    if let Some(ttl) = cli_value(sub_matches, "session_ttl") {
+        sessions.ttl = Some(
+            ttl.trim_end_matches('s')
+                .parse()
+                .expect("Invalid session ttl"),
+        );
+    }
+    let mut proxy = file_config.proxy.clone().unwrap_or_default();
+    if let Some(target) = cli_value(sub_matches, "target") {
+        proxy.target = Some(target);
+    }
+    if let Some(replay) = cli_flag(sub_matches, "replay") {
+        proxy.replay = Some(replay);
+    }
+    if let Some(names) = cli_values(sub_matches, "match") {
+        match names.iter().map(|name| MatchOn::parse(name)).collect() {
+            Some(match_on) => proxy.match_on = Some(match_on),
+            None => {
+                eprintln!("Invalid --match. Use any of method,path,query,body");
+                process::exit(1);
+            }
+        }
+    }
+    if mode == "proxy" && proxy.target.is_none() && !proxy.replay.unwrap_or(false) {
+        eprintln!("Proxy mode needs --target to record or --replay to serve recordings");
+        process::exit(1);
+    }
+    let persist = cli_flag(sub_matches, "persist")
+        .or(file_config.persist)
+        .unwrap_or(false);
+    let persist_to = cli_value(sub_matches, "persist_to").or(file_config.persist_to.clone());
+    let auto_generate_enabled = cli_flag(sub_matches, "auto_generate_data")
+        .or(file_config.auto_generate_data)
+        .unwrap_or(false);
+    let cors_enabled = cli_flag(sub_matches, "cors")
+        .or(file_config.cors)
+        .unwrap_or(false)
+        || file_config.cors_origins.is_some();
+    let watch = cli_flag(sub_matches, "watch")
+        .or(file_config.watch)
+        .unwrap_or(false);
+    let nulls = cli_value(sub_matches, "nulls")
+        .and_then(|n| NullsOrder::parse(&n))
+        .or(file_config.nulls)
+        .unwrap_or_default();
 
-    if let Some(ws_matches) = matches.subcommand_matches("websocket") {
-        mode = "websocket";
-        server_port = ws_matches
-            .get_one::<String>("port")
-            .unwrap()
-            .parse::<u16>()
-            .expect("Invalid port number");
-        cors_enabled = ws_matches.get_flag("cors");
-        auto_generate_enabled = ws_matches.get_flag("auto_generate_data");
-        pagination_factor = ws_matches
-            .get_one::<String>("page")
-            .unwrap()
-            .parse::<u64>()
-            .expect("Invalid page format");
-        if let Some(sort_args) = ws_matches.get_many::<String>("sort") {
-            let sort_list: Vec<String> = sort_args.map(|s| s.clone()).collect();
-            for sort_group in sort_list.chunks(3) {
-                if let [route, order, key] = sort_group {
-                    sort_rules.insert(route.clone(), (order.clone(), key.clone()));
-                }
+    let mut sort_rules: HashMap<String, (String, String)> = HashMap::new();
+    // `proxy` has no --sort
+    if let Some(sort_args) =
+        sub_matches.and_then(|m| m.try_get_many::<String>("sort").ok().flatten())
+    {
+        let sort_list: Vec<String> = sort_args.cloned().collect();
+        for sort_group in sort_list.chunks(3) {
+            if let [route, order, key] = sort_group {
+                sort_rules.insert(route.clone(), (order.clone(), key.clone()));
             }
         }
     }
+    let file_routes = RouteTable::new(file_config.routes);
 
     let mut allowed_origins = Vec::new();
+    let mut cors_file = None;
 
-    if cors_enabled {
-        let cors_file = "chimera.cors";
-        if Std_path::new(cors_file).exists() {
-            allowed_origins = tokio::fs::read_to_string(cors_file)
-                .await
-                .unwrap_or_default()
-                .lines()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect::<Vec<_>>();
-        } else {
-            warn!("CORS enabled but chimera.cors file not found. Allowing all origins.");
+    if let Some(origins) = file_config.cors_origins {
+        allowed_origins = origins;
+    } else if cors_enabled {
+        cors_file = Some(CORS_FILE.to_string());
+        match load_cors_origins().await {
+            Some(origins) => allowed_origins = origins,
+            None => {
+                warn!("CORS enabled but chimera.cors file not found. Allowing all origins.")
+            }
         }
     }
 
-    let json_content = tokio::fs::read_to_string(&json_file_path)
-        .await
-        .expect("Failed to read file");
+    // A proxy recording into a new fixture starts from an empty one
+    let json_content = if mode == "proxy" && !Std_path::new(&json_file_path).exists() {
+        "{}".to_string()
+    } else {
+        tokio::fs::read_to_string(&json_file_path)
+            .await
+            .expect("Failed to read file")
+    };
 
     // Check file extension first
-    let file_extension = Path::new(&json_file_path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
-
-    let parsed_content: Value = match file_extension.to_lowercase().as_str() {
-        "csv" => {
-            let mut reader = Reader::from_reader(json_content.as_bytes());
-            let mut schema_entries = Vec::new();
-
-            for result in reader.records() {
-                let record = result.expect("Failed to read CSV record");
-
-                if record.len() >= 4 {
-                    let path = record.get(0).unwrap_or("").to_string();
-                    let no_of_entries: u32 = record
-                        .get(1)
-                        .unwrap_or("0")
-                        .parse()
-                        .expect("Invalid no_of_entries value");
-                    let null_percentage: u8 = record
-                        .get(2)
-                        .unwrap_or("0")
-                        .parse()
-                        .expect("Invalid null_percentage value");
-                    let schema_json: Value = serde_json::from_str(record.get(3).unwrap_or("{}"))
-                        .expect("Invalid schema JSON in CSV");
-
-                    // Create each route entry
-                    let mut route_entry = Map::new();
-                    route_entry.insert("path".to_string(), Value::String(path));
-                    route_entry.insert(
-                        "no_of_entries".to_string(),
-                        Value::Number(no_of_entries.into()),
-                    );
-                    route_entry.insert("schema".to_string(), schema_json);
-                    route_entry.insert(
-                        "null_percentage".to_string(),
-                        Value::Number(null_percentage.into()),
-                    );
-
-                    schema_entries.push(Value::Object(route_entry));
-                }
-            }
-
-            let mut routes_object = Map::new();
-            routes_object.insert("routes".to_string(), Value::Array(schema_entries));
-            let routes_value = Value::Object(routes_object);
+    let file_extension = file_extension(&json_file_path);
 
-            let schema: JsonDataGeneratorSchema = serde_json::from_value(routes_value)
-                .expect("Failed to convert CSV data to schema format");
-            generate_json_from_schema(schema)
+    // Logging isn't initialised yet, so report startup failures on stderr
+    let mut parsed_content = match parse_data(&json_content, &file_extension, auto_generate_enabled)
+    {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
-        "json" => {
-            if auto_generate_enabled {
-                let schema: JsonDataGeneratorSchema = serde_json::from_str(&json_content)
-                    .expect("Invalid schema format for auto data generation");
-                generate_json_from_schema(schema)
-            } else {
-                let content: Value =
-                    serde_json::from_str(&json_content).expect("Invalid Json format");
-
-                if let Some(routes) = content.get("routes") {
-                    if routes.is_array() {
-                        error!("Please pass a data file .json for your routes as `auto-generate-data` is disabled");
-                        process::exit(1);
-                    } else {
-                        error!("Please pass a data file .json for your routes as `auto-generate-data` is disabled");
-                        process::exit(1);
-                    }
-                }
-
-                if !content.is_object() {
-                    error!("The given json file is a JSON Array! It should be a JSON Object");
-                    process::exit(1);
-                }
-                content
-            }
+    };
+    let key_order = top_level_keys(&parsed_content);
+    // Route definitions in the data file; the config file wins for the same route
+    let route_definitions = parsed_content.get(ROUTES_KEY).cloned();
+    let routes = match take_route_definitions(&mut parsed_content) {
+        Ok(data_routes) => data_routes.merged(&file_routes),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
-        _ => {
-            error!("Unsupported file format. Please provide a .json or .csv file");
+    };
+    // Only the HTTP server checks credentials
+    if mode == "http" {
+        if let Err(e) = auth.validate(routes.auth_schemes()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    // Recorded exchanges from `proxy`, kept out of the served data
+    let recordings = match take_recordings(&mut parsed_content) {
+        Ok(recordings) => recordings,
+        Err(e) => {
+            eprintln!("{}", e);
             process::exit(1);
         }
     };
 
+    // Generated data has no source formatting to preserve and must not overwrite the schema
+    let generated = auto_generate_enabled || file_extension == "csv";
+    let persist_style = if generated {
+        JsonStyle::default()
+    } else {
+        JsonStyle::detect(&json_content)
+    };
+    let persist_to = match persist_to {
+        Some(file) => Some(file),
+        None if persist && generated => Some(sidecar_path(&json_file_path).display().to_string()),
+        None if persist => Some(json_file_path.clone()),
+        None => None,
+    };
+
     let mut spaces = 0;
     let mut longest_path = 0;
 
-    if let Some((key, len)) = find_key_and_id_lengths(&parsed_content) {
+    if let Some((key, len)) = find_key_and_id_lengths(&parsed_content, &id_field, &routes) {
         spaces = len;
         longest_path = key;
     }
@@ -516,16 +1009,39 @@ async fn initialize_cmd() -> Result<Config, IOError> {
     Ok(Config {
         path: json_file_path,
         port: final_port,
-        mode: mode.to_string(),
+        mode,
         json_value: Arc::new(RwLock::new(parsed_content)),
         latency: sim_latency,
+        bandwidth,
         sort_rules,
+        nulls,
         paginate: pagination_factor,
+        envelope,
+        id_field,
+        single_as_array,
+        chaos,
+        rate_limit,
+        auth,
+        journal,
+        snapshots,
+        sessions,
+        saved_snapshots,
+        proxy,
+        recordings,
         max_request_path_id_length: spaces,
         max_request_path_len: longest_path,
-        cors_enabled: cors_enabled,
+        cors_enabled,
         logs_disabled,
         allowed_origins,
+        persist_to,
+        persist_style,
+        route_definitions,
+        key_order,
+        auto_generate: auto_generate_enabled,
+        watch,
+        cors_file,
+        routes,
+        file_routes,
     })
 }
 
@@ -572,6 +1088,11 @@ v{}
                 error!("Failed to setup websocket connection: {}", e);
             }
         }
+        "proxy" => {
+            if let Err(e) = run_proxy_server(config_data).await {
+                error!("Failed to run proxy server: {}", e);
+            }
+        }
         _ => {
             error!("PROTOCOL NOT SUPPORTED");
         }